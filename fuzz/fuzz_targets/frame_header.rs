@@ -0,0 +1,25 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::protocol::frame::FrameHeader;
+
+fuzz_target!(|data: &[u8]| {
+    let mut cursor = Cursor::new(data);
+    let Ok(parsed) = FrameHeader::parse(&mut cursor) else {
+        return;
+    };
+    let Some((header, length)) = parsed else {
+        // Nothing is consumed from an incomplete header.
+        assert_eq!(cursor.position(), 0);
+        return;
+    };
+
+    // Lengths may have been sent in a longer encoding than needed, so compare the parsed values.
+    let mut formatted = Vec::new();
+    header.format(length, &mut formatted).unwrap();
+    assert_eq!(formatted.len(), header.len(length));
+    let reparsed = FrameHeader::parse(&mut Cursor::new(&formatted)).unwrap();
+    assert_eq!(reparsed, Some((header, length)));
+});
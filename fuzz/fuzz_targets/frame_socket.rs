@@ -0,0 +1,20 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::protocol::frame::FrameSocket;
+
+/// Small enough to also hit the frame size limit.
+const MAX_FRAME_SIZE: usize = 1024;
+
+fuzz_target!(|data: &[u8]| {
+    let mut socket = FrameSocket::new(Cursor::new(data));
+    let mut read = 0;
+    while let Ok(Some(frame)) = socket.read(Some(MAX_FRAME_SIZE)) {
+        assert!(frame.payload().len() <= MAX_FRAME_SIZE);
+        read += frame.len();
+        // Frames are only made of the bytes of the stream.
+        assert!(read <= data.len());
+    }
+});
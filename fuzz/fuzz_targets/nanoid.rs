@@ -0,0 +1,22 @@
+#![no_main]
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use libfuzzer_sys::fuzz_target;
+use sturdy_spoon::fuzzing::{RoomId, UserId};
+
+fn check<T: FromStr + Display>(input: &str) {
+    let Ok(id) = input.parse::<T>() else {
+        return;
+    };
+    assert!(input
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-'));
+    assert_eq!(id.to_string(), input);
+}
+
+fuzz_target!(|input: &str| {
+    check::<RoomId>(input);
+    check::<UserId>(input);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use std::ops::ControlFlow;
+
+use libfuzzer_sys::fuzz_target;
+use sturdy_spoon::fuzzing::{parse_time, MAX_VIDEO_LEN};
+
+fuzz_target!(|input: &str| {
+    if let ControlFlow::Continue(time) = parse_time(input) {
+        assert!(time <= MAX_VIDEO_LEN);
+        // Only real, positive positions make it through.
+        let secs: f32 = input.split("|.|").next().unwrap().parse().unwrap();
+        assert!(secs >= 0.0);
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sturdy_spoon::fuzzing::check_str_packet;
+
+fuzz_target!(|input: &str| {
+    let Some((data_type, data)) = check_str_packet(input) else {
+        return;
+    };
+    assert!(!data_type.contains("-=-"));
+    assert!(!data.contains("-=-"));
+    assert!(input.starts_with(&format!("||-=-||{}-=-{}", data_type, data)));
+});
@@ -39,6 +39,9 @@ const PERMISSION_RESTRICTED = 0b000;
 const PERMISSION_CONTROLLABLE = 0b001;
 const PERMISSION_CHANGER = 0b010;
 
+// Owner tokens are refreshed once half of their two hours are over, ask well before that.
+const OWNER_REFRESH_INTERVAL = 10 * 60 * 1000;
+
 const mainView = document.getElementById("main-view");
 const jwplayerView = document.getElementById("jwplayer-view");
 const normalPlayerView = document.getElementById("normal-player-view");
@@ -70,7 +73,10 @@ let waitingForUser = {
 
 let currentCC = null;
 
-if (typeof autoConnect === "boolean" && autoConnect) {
+// Spectators only follow the room, they don't need a name.
+const spectate = new URLSearchParams(window.location.search).has("spectate");
+
+if ((typeof autoConnect === "boolean" && autoConnect) || spectate) {
     joinRoomForm.style = "display: None;";
     connectToServer();
 }
@@ -90,6 +96,12 @@ const handleMessage = function (message) {
     switch (type) {
         case "video_data":
             const video_data = JSON.parse(data[0]);
+            // Room wide `video_data` broadcasts don't carry our own permission.
+            if (typeof video_data.permission === "undefined") {
+                video_data.permission = typeof globalThis.last_video_data === "undefined"
+                    ? waitingForUser["permission"]
+                    : globalThis.last_video_data.permission;
+            }
 
             mainView.style = "";
             infoCollect.style = "display: None;";
@@ -123,6 +135,9 @@ const handleMessage = function (message) {
             console.log("Somebody left: ");
             console.log(data);
             break;
+        case "announcement":
+            alert(data.join("|.|"));
+            break;
         default:
             try {
                 // received as MS but the player wants in seconds
@@ -524,21 +539,40 @@ const addLocalCC = function () {
     addCurrentCC(tmppath, "Current CC");
 }
 
+const refreshOwnerToken = function () {
+    fetch(getPathUrl("room/" + room_data.room_id + "/refresh"), { method: "POST" })
+        .then((res) => {
+            if (!res.ok) {
+                console.log("owner token refresh failed: ", res.status);
+            }
+        })
+        .catch((e) => console.log("owner token refresh failed: ", e));
+}
+
 function connectToServer() {
     if (typeof room_data.ws_path === "undefined") {
         return;
     }
     const name = nameEl.value;
     const client = new WebSocket(getWsUrl(room_data.ws_path));
+    let ownerRefreshId = null;
     client.onopen = (e) => {
         console.log("ws opened: ", e);
-        if (name.length > 0) {
+        if (spectate) {
+            let packet = new StrPacket("spectate_room").addArgs(room_data.room_id);
+            client.send(packet.to_str());
+        } else if (name.length > 0) {
             let packet = new StrPacket("join_room").addArgs(room_data.room_id, name);
             client.send(packet.to_str());
         }
+        // Only owners connect right away, their token is kept alive for as long as they stay.
+        if (typeof autoConnect === "boolean" && autoConnect) {
+            ownerRefreshId = setInterval(refreshOwnerToken, OWNER_REFRESH_INTERVAL);
+        }
     }
     client.onclose = (e) => {
         console.log("ws closed: ", e);
+        clearInterval(ownerRefreshId);
         const reason = e.reason.length > 0 ? e.reason : "Forced closed. Code: " + e.code;
         alert("Error: " + reason);
         window.location.assign(getPathUrl());
@@ -562,4 +596,4 @@ joinRoomForm.onsubmit = (e) => {
 
 ccFileSelect.onchange = (_) => {
     addLocalCC();
-};
\ No newline at end of file
+};
\ No newline at end of file
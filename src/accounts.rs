@@ -0,0 +1,369 @@
+//! Optional local accounts, kept in an embedded database at `ACCOUNTS_DB`.
+//!
+//! Passwords are hashed with argon2, logged in browsers carry a signed session token in the
+//! `session` cookie, logging out ends them on every browser. The rooms an account created or
+//! joined are kept as its history, whichever browser it used. Without `ACCOUNTS_DB` the server
+//! only knows anonymous users, like before.
+
+use std::path::Path;
+use std::sync::OnceLock;
+
+use argon2::password_hash::{rand_core::OsRng, SaltString};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use http::StatusCode;
+use internal_server_error::InternalServerError;
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::basic_auth::Keys;
+use crate::common::utils::{get_elapsed_milis, random_token};
+
+pub const SESSION_COOKIE: &str = "session";
+pub const SESSION_EXPIRATION: u128 = 7 * 24 * 3600 * 1000; // 7 days
+
+/// Accounts keyed by their normalized username, values are json.
+const ACCOUNTS: TableDefinition<&str, &[u8]> = TableDefinition::new("accounts");
+/// The visits of each account, keyed like [`ACCOUNTS`], values are json lists.
+const HISTORY: TableDefinition<&str, &[u8]> = TableDefinition::new("history");
+/// Visits kept per account, the oldest ones are forgotten.
+pub const MAX_HISTORY: usize = 50;
+/// Coming back to the latest room within this is the same visit, it isn't written again.
+const VISIT_REFRESH: u128 = 10 * 60 * 1000; // 10 mins
+
+/// Differs from the one of owner tokens, so neither passes for the other.
+const SESSION_SUB: &str = "session@spoon.com";
+const COMPANY: &str = "STURDY_SPOON";
+
+#[derive(Debug, Error, InternalServerError)]
+pub enum AccountError {
+    #[error("Accounts aren't enabled on this server.")]
+    #[code(StatusCode::NOT_FOUND)]
+    Disabled,
+    #[error("The username is already taken.")]
+    #[code(StatusCode::CONFLICT)]
+    UsernameTaken,
+    #[error("Wrong username or password.")]
+    #[code(StatusCode::UNAUTHORIZED)]
+    WrongCredentials,
+    #[error("You need to log in first.")]
+    #[code(StatusCode::UNAUTHORIZED)]
+    NotLoggedIn,
+    #[error("The spcified account doesn't exist.")]
+    #[code(StatusCode::NOT_FOUND)]
+    NoAccount,
+    #[error("This account is banned.")]
+    #[code(StatusCode::FORBIDDEN)]
+    Banned,
+    #[error("The account store failed.")]
+    #[code(StatusCode::INTERNAL_SERVER_ERROR)]
+    Storage,
+}
+
+impl<E: Into<redb::Error>> From<E> for AccountError {
+    fn from(err: E) -> Self {
+        log::error!("account store error: {}", err.into());
+        AccountError::Storage
+    }
+}
+
+/// A hash of a password nobody knows, unknown usernames are checked against it so they take as
+/// long to turn away as wrong passwords.
+fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(random_token().as_bytes(), &salt)
+            .expect("Shouldn't fail")
+            .to_string()
+    })
+}
+
+/// Usernames are compared without caring about the case.
+pub fn normalize_username(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Account {
+    /// As typed at registration, see [`Account::key`] to compare it.
+    pub username: String,
+    pub display_name: String,
+    password_hash: String,
+    #[serde(default)]
+    pub banned: bool,
+    pub created: u128,
+    /// Bumped when logging out, the sessions given before stop working.
+    #[serde(default)]
+    session_generation: u32,
+}
+
+impl Account {
+    /// The normalized username, identifying the account in tokens and connections.
+    pub fn key(&self) -> String {
+        normalize_username(&self.username)
+    }
+}
+
+/// A room in the history of an account.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Visit {
+    pub room_id: String,
+    pub name: String,
+    /// Whether the account owned the room.
+    pub owner: bool,
+    pub at: u128,
+}
+
+impl Visit {
+    pub fn new(room_id: String, name: String, owner: bool) -> Self {
+        Self {
+            room_id,
+            name,
+            owner,
+            at: get_elapsed_milis(),
+        }
+    }
+}
+
+pub struct Accounts {
+    db: Database,
+}
+
+impl Accounts {
+    /// Opens the store at `ACCOUNTS_DB`, accounts are disabled when it isn't set.
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var("ACCOUNTS_DB")
+            .ok()
+            .filter(|path| !path.is_empty())?;
+        match Self::open(Path::new(&path)) {
+            Ok(accounts) => Some(accounts),
+            Err(err) => panic!("failed to open the accounts at {}: {}", path, err),
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Self, AccountError> {
+        let db = Database::create(path)?;
+        let tx = db.begin_write()?;
+        tx.open_table(ACCOUNTS)?;
+        tx.open_table(HISTORY)?;
+        tx.commit()?;
+        // Hashed now, the first unknown username would take twice as long otherwise.
+        dummy_hash();
+        Ok(Self { db })
+    }
+
+    pub fn get(&self, username: &str) -> Result<Option<Account>, AccountError> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(ACCOUNTS)?;
+        let Some(value) = table.get(normalize_username(username).as_str())? else {
+            return Ok(None);
+        };
+        serde_json::from_slice(value.value())
+            .map(Some)
+            .map_err(|err| {
+                log::error!("account {} is corrupted: {}", username, err);
+                AccountError::Storage
+            })
+    }
+
+    fn put(&self, account: &Account, create: bool) -> Result<(), AccountError> {
+        let value = serde_json::to_vec(account).expect("Shouldn't fail");
+        let key = account.key();
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(ACCOUNTS)?;
+            if create && table.get(key.as_str())?.is_some() {
+                return Err(AccountError::UsernameTaken);
+            }
+            table.insert(key.as_str(), value.as_slice())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Creates the account, hashing is slow on purpose so it should run on a blocking thread.
+    pub fn register(
+        &self,
+        username: &str,
+        password: &str,
+        display_name: &str,
+    ) -> Result<Account, AccountError> {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|err| {
+                log::error!("failed to hash a password: {}", err);
+                AccountError::Storage
+            })?
+            .to_string();
+        let account = Account {
+            username: username.trim().to_owned(),
+            display_name: display_name.trim().to_owned(),
+            password_hash,
+            banned: false,
+            created: get_elapsed_milis(),
+            session_generation: 0,
+        };
+        self.put(&account, true)?;
+        Ok(account)
+    }
+
+    /// Checks the password of the account, as slow as `register` whether the account exists or
+    /// not.
+    pub fn login(&self, username: &str, password: &str) -> Result<Account, AccountError> {
+        let account = self.get(username)?;
+        let verified = {
+            let password_hash = match &account {
+                Some(account) => account.password_hash.as_str(),
+                None => dummy_hash(),
+            };
+            let hash = PasswordHash::new(password_hash).map_err(|err| {
+                log::error!("account {} has a bad hash: {}", username, err);
+                AccountError::Storage
+            })?;
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        };
+        let Some(account) = account.filter(|_| verified) else {
+            return Err(AccountError::WrongCredentials);
+        };
+        if account.banned {
+            return Err(AccountError::Banned);
+        }
+        Ok(account)
+    }
+
+    pub fn set_display_name(
+        &self,
+        username: &str,
+        display_name: &str,
+    ) -> Result<Account, AccountError> {
+        let mut account = self.get(username)?.ok_or(AccountError::NoAccount)?;
+        account.display_name = display_name.trim().to_owned();
+        self.put(&account, false)?;
+        Ok(account)
+    }
+
+    pub fn set_banned(&self, username: &str, banned: bool) -> Result<Account, AccountError> {
+        let mut account = self.get(username)?.ok_or(AccountError::NoAccount)?;
+        account.banned = banned;
+        self.put(&account, false)?;
+        Ok(account)
+    }
+
+    /// Ends every session of the account, on every browser.
+    pub fn end_sessions(&self, username: &str) -> Result<(), AccountError> {
+        let mut account = self.get(username)?.ok_or(AccountError::NoAccount)?;
+        account.session_generation = account.session_generation.wrapping_add(1);
+        self.put(&account, false)
+    }
+
+    /// The rooms of the account, the most recent visit first.
+    pub fn history(&self, account: &str) -> Result<Vec<Visit>, AccountError> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(HISTORY)?;
+        let Some(value) = table.get(account)? else {
+            return Ok(Vec::new());
+        };
+        serde_json::from_slice(value.value()).map_err(|err| {
+            log::error!("history of {} is corrupted: {}", account, err);
+            AccountError::Storage
+        })
+    }
+
+    /// Puts the room first in the history of the account, it's only listed once. Writes wait for
+    /// the disk so it should run on a blocking thread.
+    pub fn record_visit(&self, account: &str, mut visit: Visit) -> Result<(), AccountError> {
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(HISTORY)?;
+            let mut history: Vec<Visit> = match table.get(account)? {
+                Some(value) => serde_json::from_slice(value.value()).unwrap_or_else(|err| {
+                    log::warn!(
+                        "history of {} is corrupted, starting over: {}",
+                        account,
+                        err
+                    );
+                    Vec::new()
+                }),
+                None => Vec::new(),
+            };
+            // Reloading the room page shouldn't cost a write each time.
+            let same_visit = history.first().is_some_and(|latest| {
+                latest.room_id == visit.room_id
+                    && (latest.owner || !visit.owner)
+                    && visit.at.saturating_sub(latest.at) < VISIT_REFRESH
+            });
+            if same_visit {
+                drop(table);
+                tx.abort()?;
+                return Ok(());
+            }
+            if let Some(index) = history
+                .iter()
+                .position(|previous| previous.room_id == visit.room_id)
+            {
+                visit.owner |= history.remove(index).owner;
+            }
+            history.insert(0, visit);
+            history.truncate(MAX_HISTORY);
+            let value = serde_json::to_vec(&history).expect("Shouldn't fail");
+            table.insert(account, value.as_slice())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The account of a session token, banned accounts included so they can be turned away.
+    /// Sessions ended by a logout don't count. Reads can wait for the disk as well.
+    pub fn session_account(&self, token: &str, keys: &Keys) -> Option<Account> {
+        let session = Session::from_token(token, keys).ok()?;
+        if get_elapsed_milis() > session.exp {
+            return None;
+        }
+        self.get(&session.account)
+            .ok()
+            .flatten()
+            .filter(|account| account.session_generation == session.generation)
+    }
+}
+
+/// Claims of the `session` cookie.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub account: String,
+    /// The [`Account::session_generation`] the session was given with.
+    #[serde(default)]
+    generation: u32,
+    sub: String,
+    company: String,
+    pub exp: u128,
+}
+
+impl Session {
+    pub fn new(account: &Account) -> Self {
+        Self {
+            account: account.key(),
+            generation: account.session_generation,
+            sub: SESSION_SUB.into(),
+            company: COMPANY.into(),
+            exp: get_elapsed_milis() + SESSION_EXPIRATION,
+        }
+    }
+
+    pub fn from_token(token: &str, keys: &Keys) -> Result<Self, jsonwebtoken::errors::Error> {
+        let mut validation = jsonwebtoken::Validation::default();
+        validation.sub = Some(SESSION_SUB.into());
+        let session = jsonwebtoken::decode::<Session>(token, &keys.decoding, &validation)?;
+        Ok(session.claims)
+    }
+
+    #[inline]
+    pub fn encode(&self, keys: &Keys) -> String {
+        jsonwebtoken::encode(&jsonwebtoken::Header::default(), &self, &keys.encoding)
+            .expect("Shouldn't fail")
+    }
+}
@@ -1,9 +1,12 @@
-use std::net::IpAddr;
-
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use jsonwebtoken::{DecodingKey, EncodingKey};
+use ring::{constant_time, digest};
 use serde::{Deserialize, Serialize};
 
-use crate::common::{utils::get_elapsed_milis, Id};
+use crate::common::{
+    utils::{get_elapsed_milis, random_token},
+    RoomId,
+};
 
 pub struct Keys {
     pub encoding: EncodingKey,
@@ -21,19 +24,36 @@ impl Keys {
 
 pub const EXPIRATION: u128 = 2 * 3600 * 1000; // 2 hours
 pub const CHECKED_AUTH_EXPIRATION: u128 = 5 * 60 * 1000; // 5mins
+pub const DEVICE_EXPIRATION: u128 = 30 * 24 * 3600 * 1000; // 30 days
+pub const API_TOKEN_EXPIRATION: u128 = 24 * 3600 * 1000; // 24 hours
 
 pub const OWNER_AUTH_COOKIE: &str = "owner_auth";
 pub const OWNER_AUTH_CHECKED_COOKIE: &str = "checked_auth";
+/// Random secret of the browser, owner tokens are only worth something next to it.
+pub const DEVICE_COOKIE: &str = "device_secret";
 
 const SUB: &str = "sturdy@spoon.com";
+const API_SUB: &str = "api@spoon.com";
 const COMPANY: &str = "STURDY_SPOON";
 
+/// A new secret for a browser.
+pub fn new_device_secret() -> String {
+    random_token()
+}
+
+fn hash_device_secret(device_secret: &str) -> String {
+    URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, device_secret.as_bytes()))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct OwnerAuth {
     pub username: String,
-    pub room_id: Id,
-    pub ip_addr: IpAddr,
-    pub user_agent: String,
+    pub room_id: RoomId,
+    /// SHA-256 of the device secret, the token itself doesn't give the secret away.
+    device: String,
+    /// The account which created the room, its owner on any device it logs in from.
+    #[serde(default)]
+    pub account: Option<String>,
     sub: String,
     company: String,
     pub exp: u128,
@@ -42,31 +62,55 @@ pub struct OwnerAuth {
 impl OwnerAuth {
     pub fn new(
         username: String,
-        room_id: Id,
-        ip_addr: IpAddr,
-        user_agent: String,
+        room_id: RoomId,
+        device_secret: &str,
+        account: Option<String>,
         exp: u128,
     ) -> Self {
         Self {
             username,
             room_id,
-            ip_addr,
-            user_agent,
+            device: hash_device_secret(device_secret),
+            account,
             sub: SUB.into(),
             company: COMPANY.into(),
             exp,
         }
     }
 
+    /// A token for the bots and scripts controlling the room over the REST API. It isn't bound
+    /// to a device, whoever holds it controls the room until it expires, so it's only accepted
+    /// as a bearer token by the control endpoints, never as the owner cookie.
+    pub fn new_api(room_id: RoomId, exp: u128) -> Self {
+        Self {
+            username: String::new(),
+            room_id,
+            device: String::new(),
+            account: None,
+            sub: API_SUB.into(),
+            company: COMPANY.into(),
+            exp,
+        }
+    }
+
     pub fn from_token<S: AsRef<str>>(
         token: S,
         keys: &Keys,
     ) -> Result<Self, jsonwebtoken::errors::Error> {
-        let auth = jsonwebtoken::decode::<OwnerAuth>(
-            token.as_ref(),
-            &keys.decoding,
-            &jsonwebtoken::Validation::default(),
-        )?;
+        Self::decode(token.as_ref(), keys, SUB)
+    }
+
+    pub fn from_api_token<S: AsRef<str>>(
+        token: S,
+        keys: &Keys,
+    ) -> Result<Self, jsonwebtoken::errors::Error> {
+        Self::decode(token.as_ref(), keys, API_SUB)
+    }
+
+    fn decode(token: &str, keys: &Keys, sub: &str) -> Result<Self, jsonwebtoken::errors::Error> {
+        let mut validation = jsonwebtoken::Validation::default();
+        validation.sub = Some(sub.into());
+        let auth = jsonwebtoken::decode::<OwnerAuth>(token, &keys.decoding, &validation)?;
         Ok(auth.claims)
     }
 
@@ -76,16 +120,48 @@ impl OwnerAuth {
             .expect("Shouldn't fail")
     }
 
+    /// Whether the token is still good for the browser with `device_secret`, logged in with
+    /// `account` (normalized).
     #[inline]
-    pub fn is_valid(&self, addr: IpAddr, user_agent: &String) -> bool {
+    pub fn is_valid(&self, device_secret: Option<&str>, account: Option<&str>) -> bool {
         if get_elapsed_milis() > self.exp {
             return false;
         }
-        self.ip_addr == addr && self.user_agent == *user_agent
+        if self.account.is_some() && self.account.as_deref() == account {
+            return true;
+        }
+        device_secret.is_some_and(|device_secret| {
+            let device = hash_device_secret(device_secret);
+            constant_time::verify_slices_are_equal(self.device.as_bytes(), device.as_bytes())
+                .is_ok()
+        })
+    }
+
+    #[inline]
+    pub fn is_valid_room_id(
+        &self,
+        device_secret: Option<&str>,
+        account: Option<&str>,
+        room_id: &RoomId,
+    ) -> bool {
+        self.is_valid(device_secret, account) && self.room_id == *room_id
     }
 
+    /// Whether the API token is still good for `room_id`.
     #[inline]
-    pub fn is_valid_room_id(&self, addr: IpAddr, user_agent: &String, room_id: &Id) -> bool {
-        self.is_valid(addr, user_agent) && self.room_id == *room_id
+    pub fn is_valid_api(&self, room_id: &RoomId) -> bool {
+        get_elapsed_milis() <= self.exp && self.room_id == *room_id
+    }
+
+    /// Whether half of the token's life is over, owners still around get a fresh one then.
+    #[inline]
+    pub fn needs_refresh(&self) -> bool {
+        self.exp.saturating_sub(get_elapsed_milis()) < EXPIRATION / 2
+    }
+
+    /// The same token, valid for another `EXPIRATION`.
+    pub fn refreshed(mut self) -> Self {
+        self.exp = get_elapsed_milis() + EXPIRATION;
+        self
     }
 }
@@ -0,0 +1,246 @@
+//! The address of the client behind our reverse proxies.
+//!
+//! Requests coming from one of the `TRUSTED_PROXIES` (comma separated addresses or CIDRs, like
+//! `127.0.0.1/32,::1`) are attributed to the address the proxies put in `Forwarded`,
+//! `X-Forwarded-For` or `X-Real-IP`, in that order of preference. The chain is read from the
+//! closest hop, skipping our proxies, so a client can't pass for someone else by sending the
+//! headers itself. Every other request is attributed to the peer of the connection.
+//!
+//! The scheme the client used is told the same way, by `proto` in `Forwarded` or by
+//! `X-Forwarded-Proto`. The server only speaks plain http itself.
+
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts, State};
+use axum::http::{request::Parts, HeaderMap, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+
+struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for IpNet {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = IpAddr::from_str(addr.trim())
+            .map_err(|_| ())?
+            .to_canonical();
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().map_err(|_| ())?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            return Err(());
+        }
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl IpNet {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The reverse proxies allowed to tell who the client is.
+#[derive(Default)]
+pub struct TrustedProxies {
+    nets: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    pub fn from_env() -> Self {
+        let Ok(proxies) = std::env::var("TRUSTED_PROXIES") else {
+            return Self::default();
+        };
+        let nets = proxies
+            .split(',')
+            .map(str::trim)
+            .filter(|net| !net.is_empty())
+            .filter_map(|net| {
+                let parsed = IpNet::from_str(net).ok();
+                if parsed.is_none() {
+                    log::warn!("TRUSTED_PROXIES: {:?} isn't an address or a CIDR", net);
+                }
+                parsed
+            })
+            .collect();
+        Self { nets }
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.nets.iter().any(|net| net.contains(ip))
+    }
+
+    /// Finds the client of a request coming from `peer`, the port is the one of the connection
+    /// to the proxy unless the proxy told the client's.
+    pub fn resolve(&self, peer: SocketAddr, headers: &HeaderMap) -> SocketAddr {
+        if !self.is_trusted(peer.ip()) {
+            return peer;
+        }
+        let chain = forwarded_chain(headers);
+        let mut client = peer;
+        for hop in chain.iter().rev() {
+            // Past what we can make sense of, nothing tells the hop isn't made up.
+            let Some(hop) = hop else {
+                break;
+            };
+            client = match *hop {
+                Hop::Ip(ip) => SocketAddr::new(ip, peer.port()),
+                Hop::Socket(addr) => addr,
+            };
+            if !self.is_trusted(client.ip()) {
+                break;
+            }
+        }
+        client
+    }
+
+    /// Finds the scheme the client of a request coming from `peer` used, the value added by the
+    /// closest proxy is the one believed.
+    pub fn scheme(&self, peer: SocketAddr, headers: &HeaderMap) -> &'static str {
+        if !self.is_trusted(peer.ip()) {
+            return "http";
+        }
+        let forwarded = headers
+            .get_all("forwarded")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("proto"))
+                    .map(|(_, proto)| proto)
+            })
+            .next_back();
+        let proto = forwarded.or_else(|| {
+            headers
+                .get_all("x-forwarded-proto")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .next_back()
+        });
+        match proto {
+            Some(proto) if proto.trim().trim_matches('"').eq_ignore_ascii_case("https") => "https",
+            _ => "http",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Hop {
+    Ip(IpAddr),
+    Socket(SocketAddr),
+}
+
+impl Hop {
+    fn parse(hop: &str) -> Option<Self> {
+        let hop = hop.trim().trim_matches('"');
+        if let Ok(ip) = IpAddr::from_str(hop) {
+            return Some(Hop::Ip(ip));
+        }
+        if let Ok(addr) = SocketAddr::from_str(hop) {
+            return Some(Hop::Socket(addr));
+        }
+        // `Forwarded` puts brackets around IPv6 addresses even without a port.
+        let ip = hop.strip_prefix('[')?.strip_suffix(']')?;
+        IpAddr::from_str(ip).ok().map(Hop::Ip)
+    }
+}
+
+/// The addresses the request went through, from the client to the last proxy. `None` stands
+/// for the hops which aren't addresses, like `unknown` or obfuscated identifiers.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<Hop>> {
+    let values = |name: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>()
+    };
+
+    let forwarded = values("forwarded");
+    if !forwarded.is_empty() {
+        return forwarded
+            .into_iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, hop)| Hop::parse(hop))
+            })
+            .collect();
+    }
+    let forwarded_for = values("x-forwarded-for");
+    if !forwarded_for.is_empty() {
+        return forwarded_for.into_iter().map(Hop::parse).collect();
+    }
+    values("x-real-ip").into_iter().map(Hop::parse).collect()
+}
+
+/// Where the request comes from, see the module documentation.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientAddr(pub SocketAddr);
+
+/// The scheme the client used, `https` when our proxies terminated TLS for it.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientScheme(pub &'static str);
+
+/// Middleware resolving the `ClientAddr` and the `ClientScheme` of every request.
+pub async fn resolve<B>(
+    State(proxies): State<&'static TrustedProxies>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let client = proxies.resolve(peer, request.headers());
+    let scheme = proxies.scheme(peer, request.headers());
+    request.extensions_mut().insert(ClientAddr(client));
+    request.extensions_mut().insert(ClientScheme(scheme));
+    next.run(request).await
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientAddr {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        if let Some(client) = parts.extensions.get::<ClientAddr>() {
+            return Ok(*client);
+        }
+        parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| ClientAddr(*peer))
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "Unknown client address"))
+    }
+}
@@ -1,11 +1,13 @@
 pub mod nanoid;
 pub mod ubucket;
 pub mod utils;
+pub mod validation;
 
-pub type Id = nanoid::NanoId;
-pub type HashContainer<T> = ubucket::UBucket<Id, T>;
+pub type RoomId = nanoid::NanoId<nanoid::RoomIdFormat>;
+pub type UserId = nanoid::NanoId<nanoid::UserIdFormat>;
+pub type HashContainer<K, T> = ubucket::UBucket<K, T>;
 
 #[inline(always)]
-pub fn get_new_id() -> Id {
+pub fn get_new_id<F: nanoid::IdFormat>() -> nanoid::NanoId<F> {
     nanoid::NanoId::new()
 }
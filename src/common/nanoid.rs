@@ -1,50 +1,153 @@
-use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub struct LengthNotMatched;
+pub enum InvalidNanoId {
+    LengthNotMatched,
+    NotInAlphabet,
+}
 
-impl std::fmt::Display for LengthNotMatched {
+impl std::fmt::Display for InvalidNanoId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "The provided `&str` didn't match length.")
+        match self {
+            Self::LengthNotMatched => write!(f, "The provided `&str` didn't match length."),
+            Self::NotInAlphabet => write!(
+                f,
+                "The provided `&str` has characters outside of the nanoid alphabet."
+            ),
+        }
     }
 }
 
-// To nicely align it
-const NANOID_BYTES_LEN: usize = std::mem::size_of::<usize>() * 2;
+/// Longest id any [`IdFormat`] can ask for.
+pub const MAX_ID_LEN: usize = 32;
+
+/// Length and alphabet of a kind of id.
+pub trait IdFormat: 'static {
+    /// At most [`MAX_ID_LEN`].
+    const LEN: usize;
+    /// Only ascii characters, so every id is exactly `LEN` bytes.
+    const ALPHABET: &'static [char];
+}
+
+/// Room ids end up in links people share, they are kept short.
+pub struct RoomIdFormat;
+
+impl IdFormat for RoomIdFormat {
+    const LEN: usize = 16;
+    const ALPHABET: &'static [char] = &nanoid::alphabet::SAFE;
+}
+
+/// Users, and the one time owner tokens handed to them, are never typed by anyone.
+pub struct UserIdFormat;
+
+impl IdFormat for UserIdFormat {
+    const LEN: usize = 21;
+    const ALPHABET: &'static [char] = &nanoid::alphabet::SAFE;
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// A random id in the shape of `F`, only ever built from characters of its alphabet.
 #[repr(transparent)]
-pub struct NanoId([u8; NANOID_BYTES_LEN]);
+pub struct NanoId<F: IdFormat> {
+    bytes: [u8; MAX_ID_LEN],
+    _format: PhantomData<fn() -> F>,
+}
+
+impl<F: IdFormat> NanoId<F> {
+    const LEN: usize = {
+        assert!(
+            F::LEN <= MAX_ID_LEN,
+            "ids can't be longer than `MAX_ID_LEN`"
+        );
+        F::LEN
+    };
 
-impl NanoId {
     pub fn new() -> Self {
-        // It guarantees it will generete "sizeof(usize) * 2" u8(s)
-        let nanoid_str = nanoid::nanoid!(NANOID_BYTES_LEN);
-        let mut nanoid: [u8; NANOID_BYTES_LEN] = [0; NANOID_BYTES_LEN];
-        nanoid.copy_from_slice(nanoid_str.as_bytes());
+        let nanoid_str = nanoid::format(nanoid::rngs::default, F::ALPHABET, Self::LEN);
+        Self::from_ascii(nanoid_str.as_bytes())
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..Self::LEN])
+            .expect("Bug: ids are only built from their ascii alphabet")
+    }
 
-        Self(nanoid)
+    fn from_ascii(s: &[u8]) -> Self {
+        let mut bytes = [0; MAX_ID_LEN];
+        bytes[..Self::LEN].copy_from_slice(s);
+        Self {
+            bytes,
+            _format: PhantomData,
+        }
     }
 }
 
-impl std::fmt::Display for NanoId {
+impl<F: IdFormat> Default for NanoId<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: IdFormat> Clone for NanoId<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F: IdFormat> Copy for NanoId<F> {}
+
+impl<F: IdFormat> PartialEq for NanoId<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl<F: IdFormat> Eq for NanoId<F> {}
+
+impl<F: IdFormat> Hash for NanoId<F> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bytes.hash(state)
+    }
+}
+
+impl<F: IdFormat> std::fmt::Debug for NanoId<F> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // Safety: The `NanoId::new()` confirms all the generated bytes are valid utf8 bytes.
-        unsafe { write!(f, "{}", std::str::from_utf8(&self.0).unwrap_unchecked()) }
+        f.debug_tuple("NanoId").field(&self.as_str()).finish()
     }
 }
 
-impl std::str::FromStr for NanoId {
-    type Err = LengthNotMatched;
+impl<F: IdFormat> std::fmt::Display for NanoId<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<F: IdFormat> std::str::FromStr for NanoId<F> {
+    type Err = InvalidNanoId;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != NANOID_BYTES_LEN {
-            return Err(LengthNotMatched);
+        if s.len() != Self::LEN {
+            return Err(InvalidNanoId::LengthNotMatched);
+        }
+        if !s.chars().all(|c| F::ALPHABET.contains(&c)) {
+            return Err(InvalidNanoId::NotInAlphabet);
         }
 
-        let mut nanoid: [u8; NANOID_BYTES_LEN] = [0; NANOID_BYTES_LEN];
-        nanoid.copy_from_slice(s.as_bytes());
+        Ok(Self::from_ascii(s.as_bytes()))
+    }
+}
+
+impl<F: IdFormat> Serialize for NanoId<F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
 
-        Ok(NanoId(nanoid))
+impl<'de, F: IdFormat> Deserialize<'de> for NanoId<F> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
     }
 }
@@ -1,4 +1,5 @@
 use ahash::RandomState;
+use scc::ebr::Barrier;
 use scc::HashIndex;
 use std::{borrow::Borrow, hash::Hash};
 
@@ -36,4 +37,17 @@ impl<K: Clone + Eq + Hash + 'static, V: Clone + 'static> UBucket<K, V> {
     {
         self.inner.read(key, reader)
     }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Visits every entry, the entries inserted or removed meanwhile may or may not be visited.
+    pub fn scan<F: FnMut(&K, &V)>(&self, mut scanner: F) {
+        let barrier = Barrier::new();
+        for (k, v) in self.inner.iter(&barrier) {
+            scanner(k, v);
+        }
+    }
 }
@@ -1,6 +1,44 @@
+/// Milliseconds since the unix epoch.
+///
+/// The wall clock is only read once, the time then moves with tokio's clock so it never goes
+/// backwards and tests can pause and advance it.
 pub fn get_elapsed_milis() -> u128 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::SystemTime::UNIX_EPOCH)
-        .expect("Shouldn't happen?")
-        .as_millis()
+    static START: std::sync::OnceLock<(tokio::time::Instant, u128)> = std::sync::OnceLock::new();
+    let (start, start_milis) = START.get_or_init(|| {
+        let milis = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .expect("Shouldn't happen?")
+            .as_millis();
+        (tokio::time::Instant::now(), milis)
+    });
+    start_milis + start.elapsed().as_millis()
+}
+
+/// 32 random bytes encoded as url safe base64, for secrets handed to browsers.
+pub fn random_token() -> String {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use ring::rand::SecureRandom;
+
+    let mut token = [0; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut token)
+        .expect("the system has no randomness");
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+/// Wraps user supplied content (names, urls, packets...) so it only ends up in the logs when
+/// `LOG_USER_CONTENT` is set, otherwise just its length is logged.
+pub struct Redacted<'a>(pub &'a str);
+
+impl std::fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        static LOG_USER_CONTENT: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+        let log_user_content = *LOG_USER_CONTENT
+            .get_or_init(|| std::env::var("LOG_USER_CONTENT").is_ok_and(|v| !v.is_empty()));
+        if log_user_content {
+            write!(f, "{:?}", self.0)
+        } else {
+            write!(f, "<redacted {} bytes>", self.0.len())
+        }
+    }
 }
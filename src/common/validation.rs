@@ -0,0 +1,172 @@
+//! Checks of the text users hand to the server, shared by the HTTP api and the WebSocket join.
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use thiserror::Error;
+
+pub const MAX_ROOM_NAME_LEN: usize = 64;
+pub const MAX_USER_NAME_LEN: usize = 32;
+pub const MAX_URL_LEN: usize = 2048;
+pub const MIN_USERNAME_LEN: usize = 3;
+pub const MAX_USERNAME_LEN: usize = 32;
+pub const MIN_PASSWORD_LEN: usize = 8;
+pub const MAX_PASSWORD_LEN: usize = 128;
+
+/// Names which would pass for the server or the staff, compared without caring about the case.
+const RESERVED_NAMES: [&str; 6] = [
+    "admin",
+    "administrator",
+    "moderator",
+    "server",
+    "spectator",
+    "system",
+];
+
+/// The separators of string packets, names are sent in packets.
+const PACKET_SEPARATORS: [&str; 3] = ["||-=-||", "-=-", "|.|"];
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidField {
+    #[error("must not be empty")]
+    Empty,
+    #[error("must be at least {0} characters")]
+    TooShort(usize),
+    #[error("must be at most {0} characters")]
+    TooLong(usize),
+    #[error("must only have letters, digits, dashes or underscores")]
+    NotAlphanumeric,
+    #[error("must not have control characters or packet separators")]
+    BadCharacters,
+    #[error("is reserved")]
+    Reserved,
+    #[error("must be an http or https url")]
+    BadUrl,
+    #[error("must be between {0} and {1}")]
+    OutOfRange(u32, u32),
+}
+
+/// A room name, or anything else displayed to the users of a room.
+pub fn check_name(name: &str, max_len: usize) -> Result<(), InvalidField> {
+    if name.trim().is_empty() {
+        return Err(InvalidField::Empty);
+    }
+    if name.chars().count() > max_len {
+        return Err(InvalidField::TooLong(max_len));
+    }
+    if name.chars().any(char::is_control) || PACKET_SEPARATORS.iter().any(|s| name.contains(s)) {
+        return Err(InvalidField::BadCharacters);
+    }
+    Ok(())
+}
+
+/// The name a user is known by in a room.
+pub fn check_user_name(name: &str) -> Result<(), InvalidField> {
+    check_name(name, MAX_USER_NAME_LEN)?;
+    let name = name.trim();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name))
+    {
+        return Err(InvalidField::Reserved);
+    }
+    Ok(())
+}
+
+/// The name an account logs in with, unlike display names it's kept plain.
+pub fn check_username(username: &str) -> Result<(), InvalidField> {
+    let len = username.chars().count();
+    if len < MIN_USERNAME_LEN {
+        return Err(InvalidField::TooShort(MIN_USERNAME_LEN));
+    }
+    if len > MAX_USERNAME_LEN {
+        return Err(InvalidField::TooLong(MAX_USERNAME_LEN));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(InvalidField::NotAlphanumeric);
+    }
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(username))
+    {
+        return Err(InvalidField::Reserved);
+    }
+    Ok(())
+}
+
+pub fn check_password(password: &str) -> Result<(), InvalidField> {
+    let len = password.chars().count();
+    if len < MIN_PASSWORD_LEN {
+        return Err(InvalidField::TooShort(MIN_PASSWORD_LEN));
+    }
+    if len > MAX_PASSWORD_LEN {
+        return Err(InvalidField::TooLong(MAX_PASSWORD_LEN));
+    }
+    Ok(())
+}
+
+/// An absolute http(s) url, the players can't load anything else.
+pub fn check_url(url: &str) -> Result<(), InvalidField> {
+    if url.is_empty() {
+        return Err(InvalidField::Empty);
+    }
+    if url.len() > MAX_URL_LEN {
+        return Err(InvalidField::TooLong(MAX_URL_LEN));
+    }
+    let Ok(uri) = url.parse::<http::Uri>() else {
+        return Err(InvalidField::BadUrl);
+    };
+    let http = matches!(uri.scheme_str(), Some("http" | "https"));
+    if !http || uri.host().is_none_or(str::is_empty) {
+        return Err(InvalidField::BadUrl);
+    }
+    Ok(())
+}
+
+pub fn check_range(value: i64, min: u32, max: u32) -> Result<(), InvalidField> {
+    if value < i64::from(min) || value > i64::from(max) {
+        return Err(InvalidField::OutOfRange(min, max));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// Every problem found in a payload, answered with `422 Unprocessable Entity` and a json body
+/// like `{"errors": [{"field": "name", "message": "must not be empty"}]}`.
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    /// Records the error of `field`, if any.
+    pub fn check(&mut self, field: &'static str, result: Result<(), InvalidField>) {
+        if let Err(err) = result {
+            self.errors.push(FieldError {
+                field,
+                message: err.to_string(),
+            });
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl IntoResponse for ValidationErrors {
+    fn into_response(self) -> axum::response::Response {
+        (StatusCode::UNPROCESSABLE_ENTITY, Json(self)).into_response()
+    }
+}
@@ -0,0 +1,12 @@
+//! Entry points for the fuzz targets in `fuzz/`, to reach the parsers of untrusted input which
+//! aren't part of the public api. Only built with the `fuzzing` feature.
+
+use std::ops::ControlFlow;
+
+pub use crate::common::{RoomId, UserId};
+pub use crate::ws_handler::{check_str_packet, MAX_VIDEO_LEN};
+
+/// Parses the time argument of a packet, as the `state`, `seek`, `play` and `pause` handlers do.
+pub fn parse_time(data: &str) -> ControlFlow<bool, usize> {
+    crate::ws_handler::user_state::parse_time(data.split("|.|"))
+}
@@ -1 +1,202 @@
+use std::future::Future;
+use std::net::{SocketAddr, TcpListener};
+use std::str::FromStr;
+
+use axum::extract::State;
+use axum::middleware;
+use axum::{headers, response::IntoResponse, routing::get, Router, Server, TypedHeader};
+
+use http::StatusCode;
+use tokio::sync::oneshot;
+use tower_cookies::{Cookie, CookieManagerLayer, Cookies};
+use tower_http::services::ServeDir;
+use ws_handler::ws_state::DEFAULT_WS;
+
+mod accounts;
+mod basic_auth;
+mod client_addr;
+mod common;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+mod metrics;
+mod oidc;
+mod origin;
+mod rate_limit;
+pub mod server_state;
 pub mod sturdy_ws;
+mod web;
+mod ws_handler;
+
+use crate::accounts::Account;
+use crate::basic_auth::{DEVICE_COOKIE, OWNER_AUTH_CHECKED_COOKIE, OWNER_AUTH_COOKIE};
+use crate::client_addr::ClientAddr;
+use crate::common::UserId;
+use crate::ws_handler::validate_and_handle_client;
+use server_state::ServerState;
+use sturdy_ws::{CloseCode, WebSocketUpgrade};
+
+const DEFAULT_DRAIN_SECS: u64 = 10;
+const DRAIN_POLL_MILLIS: u64 = 100;
+
+/// Builds the whole application, pages, REST API and WebSocket endpoint included.
+pub fn app(state: ServerState) -> Router {
+    let trusted_proxies = state.trusted_proxies;
+    let allowed_origins = state.allowed_origins;
+    Router::new()
+        .fallback_service(
+            ServeDir::new(state.get_static_dir()).append_index_html_on_directories(true),
+        )
+        .nest_service("/js", ServeDir::new(state.get_js_dir()))
+        .merge(ws_route(state.clone()))
+        .merge(web::routes(state))
+        .layer(CookieManagerLayer::new())
+        .layer(middleware::from_fn_with_state(
+            allowed_origins,
+            origin::check,
+        ))
+        .layer(middleware::from_fn_with_state(
+            trusted_proxies,
+            client_addr::resolve,
+        ))
+}
+
+/// Serves the application on `listener` until `shutdown` resolves, then tells every connected
+/// user the server is going away and waits for them to leave, up to `SHUTDOWN_DRAIN_SECS`.
+///
+/// Rooms only live in memory and don't survive the restart, persisting them isn't implemented.
+pub async fn run_server(
+    state: ServerState,
+    listener: TcpListener,
+    shutdown: impl Future<Output = ()>,
+) {
+    let ws_state = state.ws_state;
+    let app = app(state);
+
+    let drain_timeout = std::env::var("SHUTDOWN_DRAIN_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_DRAIN_SECS);
+    match listener.local_addr() {
+        Ok(addr) => log::info!("Listening on {}...", addr),
+        Err(err) => log::warn!("Listening on an unknown address: {}", err),
+    }
+
+    let (drain_tx, drain_rx) = oneshot::channel();
+    let server = Server::from_tcp(listener)
+        .expect("failed to use the listener")
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            shutdown.await;
+            ws_state.start_shutdown();
+            let disconnected = ws_state
+                .disconnect_all(CloseCode::Away, "server restarting")
+                .await;
+            log::info!(
+                "Shutting down, notified {} users and dropped {}, draining for up to {}s...",
+                disconnected.closed,
+                disconnected.dropped,
+                drain_timeout
+            );
+            let _ = drain_tx.send(());
+        });
+
+    // Hyper waits for the open responses, the event streams end once the shutdown started. It
+    // stops tracking WebSockets once upgraded though, so wait for the users to leave on our own
+    // and give up once the drain timeout is over.
+    let drained = async {
+        server.await.unwrap();
+        while ws_state.get_connected_count() > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(DRAIN_POLL_MILLIS)).await;
+        }
+    };
+    let timed_out = async {
+        if drain_rx.await.is_err() {
+            return std::future::pending().await;
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(drain_timeout)).await;
+    };
+
+    tokio::select! {
+        _ = drained => log::info!("Every connection is closed."),
+        _ = timed_out => log::warn!(
+            "Drain timeout reached, dropping {} users.",
+            ws_state.get_connected_count()
+        ),
+    }
+}
+
+fn ws_route(state: ServerState) -> Router {
+    let ws_path: &str = &format!("/{}", DEFAULT_WS);
+    Router::new()
+        .route(ws_path, get(ws_handler))
+        .route_layer(middleware::from_fn_with_state(
+            &state.rate_limits.ws_connect,
+            rate_limit::limit,
+        ))
+        .with_state(state)
+}
+
+// TODO: Move this logic into `ws_handler` or something else?
+async fn ws_handler(
+    cookies: Cookies,
+    ws: WebSocketUpgrade,
+    State(server): State<ServerState>,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ClientAddr(addr): ClientAddr,
+) -> impl IntoResponse {
+    let user_agent = if let Some(TypedHeader(user_agent)) = user_agent {
+        user_agent.to_string()
+    } else {
+        return (StatusCode::FORBIDDEN, "Unknown User agent").into_response();
+    };
+
+    log::debug!("peer={} connected with user agent: {}", addr, user_agent);
+
+    let account = server.session_account(&cookies).await;
+    if account.as_ref().is_some_and(|account| account.banned) {
+        return (StatusCode::FORBIDDEN, "This account is banned.").into_response();
+    }
+    let account_key = account.as_ref().map(Account::key);
+    let device_secret = cookies.get(DEVICE_COOKIE);
+    let owner = match cookies.get(OWNER_AUTH_CHECKED_COOKIE) {
+        Some(cookie) => match UserId::from_str(cookie.value()) {
+            Err(_) => None,
+            Ok(id) => {
+                match server
+                    .ws_state
+                    .remove_checked_auth(id, |(v, _)| {
+                        v.is_valid(
+                            device_secret.as_ref().map(Cookie::value),
+                            account_key.as_deref(),
+                        )
+                    })
+                    .await
+                    .ok()
+                {
+                    None => {
+                        cookies.remove(Cookie::named(OWNER_AUTH_COOKIE));
+                        None
+                    }
+                    v => v,
+                }
+            }
+        },
+        None => None,
+    };
+    cookies.remove(Cookie::named(OWNER_AUTH_CHECKED_COOKIE));
+
+    ws.max_write_buffer_size(server.max_write_buffer)
+        .permessage_deflate()
+        .on_upgrade(move |socket| async move {
+            validate_and_handle_client(
+                server.ws_state,
+                &server.rate_limits.ws_packets,
+                socket,
+                addr,
+                owner,
+                account,
+            )
+            .await;
+        })
+}
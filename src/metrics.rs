@@ -0,0 +1,161 @@
+//! Process wide counters exposed at `/metrics`, the gauges are read straight from `WsState`.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::sturdy_ws::stats::frame_stats;
+
+/// Which `process_*_message` handled the packet.
+#[derive(Clone, Copy)]
+pub enum Handler {
+    Privileged = 0,
+    Normal = 1,
+}
+
+const HANDLERS: [&str; 2] = ["privileged", "normal"];
+const PACKET_TYPES: [&str; 5] = ["state", "seek", "play", "pause", "unknown"];
+
+static PACKETS: [[AtomicU64; PACKET_TYPES.len()]; HANDLERS.len()] =
+    [const { [const { AtomicU64::new(0) }; PACKET_TYPES.len()] }; HANDLERS.len()];
+static RESYNCS: [AtomicU64; HANDLERS.len()] = [const { AtomicU64::new(0) }; HANDLERS.len()];
+static BROADCAST_LAGGED: AtomicU64 = AtomicU64::new(0);
+static BROADCAST_LAGGED_MESSAGES: AtomicU64 = AtomicU64::new(0);
+
+/// Counts a handled packet, packet types we don't know about are counted as `unknown` so
+/// clients can't blow up the label set.
+pub fn packet_handled(handler: Handler, packet_type: &str) {
+    let index = PACKET_TYPES[..PACKET_TYPES.len() - 1]
+        .iter()
+        .position(|t| *t == packet_type)
+        .unwrap_or(PACKET_TYPES.len() - 1);
+    PACKETS[handler as usize][index].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a `state` packet which was too far off the room and forced a resync.
+pub fn resync(handler: Handler) {
+    RESYNCS[handler as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a `broadcast_rx.recv` lag error and how many messages were skipped because of it.
+pub fn broadcast_lagged(skipped: u64) {
+    BROADCAST_LAGGED.fetch_add(1, Ordering::Relaxed);
+    BROADCAST_LAGGED_MESSAGES.fetch_add(skipped, Ordering::Relaxed);
+}
+
+pub struct Gauges {
+    pub rooms: usize,
+    pub users: usize,
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Renders everything in the Prometheus text exposition format.
+pub fn render(gauges: Gauges) -> String {
+    let mut out = String::with_capacity(4096);
+
+    family(&mut out, "sturdy_rooms", "gauge", "Rooms currently open.");
+    let _ = writeln!(out, "sturdy_rooms {}", gauges.rooms);
+
+    family(
+        &mut out,
+        "sturdy_connected_users",
+        "gauge",
+        "Connected users, spectators included.",
+    );
+    let _ = writeln!(out, "sturdy_connected_users {}", gauges.users);
+
+    family(
+        &mut out,
+        "sturdy_packets_total",
+        "counter",
+        "Client packets handled, by handler and packet type.",
+    );
+    for (handler, counters) in HANDLERS.iter().zip(&PACKETS) {
+        for (packet_type, counter) in PACKET_TYPES.iter().zip(counters) {
+            let _ = writeln!(
+                out,
+                "sturdy_packets_total{{handler=\"{}\",type=\"{}\"}} {}",
+                handler,
+                packet_type,
+                counter.load(Ordering::Relaxed)
+            );
+        }
+    }
+
+    family(
+        &mut out,
+        "sturdy_resyncs_total",
+        "counter",
+        "State packets too far off the room which forced a resync.",
+    );
+    for (handler, counter) in HANDLERS.iter().zip(&RESYNCS) {
+        let _ = writeln!(
+            out,
+            "sturdy_resyncs_total{{handler=\"{}\"}} {}",
+            handler,
+            counter.load(Ordering::Relaxed)
+        );
+    }
+
+    family(
+        &mut out,
+        "sturdy_broadcast_lagged_total",
+        "counter",
+        "Times a receiver fell behind the room broadcast.",
+    );
+    let _ = writeln!(
+        out,
+        "sturdy_broadcast_lagged_total {}",
+        BROADCAST_LAGGED.load(Ordering::Relaxed)
+    );
+    family(
+        &mut out,
+        "sturdy_broadcast_lagged_messages_total",
+        "counter",
+        "Room broadcast messages skipped by lagging receivers.",
+    );
+    let _ = writeln!(
+        out,
+        "sturdy_broadcast_lagged_messages_total {}",
+        BROADCAST_LAGGED_MESSAGES.load(Ordering::Relaxed)
+    );
+
+    let frames = frame_stats();
+    family(
+        &mut out,
+        "sturdy_ws_frames_total",
+        "counter",
+        "WebSocket frames, by direction.",
+    );
+    let _ = writeln!(
+        out,
+        "sturdy_ws_frames_total{{direction=\"received\"}} {}",
+        frames.frames_received
+    );
+    let _ = writeln!(
+        out,
+        "sturdy_ws_frames_total{{direction=\"sent\"}} {}",
+        frames.frames_sent
+    );
+    family(
+        &mut out,
+        "sturdy_ws_bytes_total",
+        "counter",
+        "WebSocket bytes including the frame headers, by direction.",
+    );
+    let _ = writeln!(
+        out,
+        "sturdy_ws_bytes_total{{direction=\"received\"}} {}",
+        frames.bytes_received
+    );
+    let _ = writeln!(
+        out,
+        "sturdy_ws_bytes_total{{direction=\"sent\"}} {}",
+        frames.bytes_sent
+    );
+
+    out
+}
@@ -0,0 +1,513 @@
+//! OpenID Connect single sign-on, enabled by `OIDC_ISSUER`.
+//!
+//! Browsers go through the authorization code flow with PKCE: `/auth/oidc/login` sends them to
+//! the issuer, which sends them back to `/auth/oidc/callback` with a code. The code is exchanged
+//! for an id token, verified against the keys the issuer publishes, and the browser is then
+//! given a signed `sso` cookie. With `OIDC_REQUIRE_FOR_CREATE` only signed in browsers can
+//! create rooms, joining them stays open to anyone.
+//!
+//! The other variables are `OIDC_CLIENT_ID`, `OIDC_REDIRECT_URL` (the public url of the
+//! callback), `OIDC_CLIENT_SECRET` for confidential clients and `OIDC_SCOPES`. The issuer is
+//! reached over https, plain http is only meant for issuers on the same host and the client
+//! secret is never sent over it to another one.
+
+use std::collections::hash_map::RandomState;
+use std::time::Duration;
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine as _,
+};
+use http::{header, Method, Request, StatusCode, Uri};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use internal_server_error::InternalServerError;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey};
+use ring::digest;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::{OnceCell, RwLock};
+
+use crate::basic_auth::Keys;
+use crate::common::utils::{get_elapsed_milis, random_token};
+
+pub const SSO_COOKIE: &str = "sso";
+/// Holds the `state` of the login in progress, the callback must come from the same browser.
+pub const LOGIN_COOKIE: &str = "oidc_login";
+pub const SSO_EXPIRATION: u128 = 12 * 3600 * 1000; // 12 hours
+pub const LOGIN_EXPIRATION: u128 = 10 * 60 * 1000; // 10 mins
+
+/// Differs from the ones of owner and session tokens, so none passes for another.
+const SSO_SUB: &str = "sso@spoon.com";
+const COMPANY: &str = "STURDY_SPOON";
+const DEFAULT_SCOPES: &str = "openid profile email";
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+/// Unknown key ids make us fetch the keys again, but not more often than this.
+const JWKS_REFRESH: u128 = 60 * 1000; // 1 min
+/// Only signatures made with a private key are accepted from the issuer.
+const ID_TOKEN_ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+#[derive(Debug, Error, InternalServerError)]
+pub enum OidcError {
+    #[error("Single sign-on isn't enabled on this server.")]
+    #[code(StatusCode::NOT_FOUND)]
+    Disabled,
+    #[error("Single sign-on is required to create rooms.")]
+    #[code(StatusCode::UNAUTHORIZED)]
+    Required,
+    #[error("You need to sign in first.")]
+    #[code(StatusCode::UNAUTHORIZED)]
+    NotSignedIn,
+    #[error("The login expired or wasn't started from this browser.")]
+    #[code(StatusCode::BAD_REQUEST)]
+    BadLogin,
+    #[error("The identity provider refused the login.")]
+    #[code(StatusCode::FORBIDDEN)]
+    Denied,
+    #[error("The identity provider sent an invalid token.")]
+    #[code(StatusCode::UNAUTHORIZED)]
+    BadIdToken,
+    #[error("The identity provider can't be reached.")]
+    #[code(StatusCode::BAD_GATEWAY)]
+    Provider,
+}
+
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+    pub require_for_create: bool,
+}
+
+impl OidcConfig {
+    /// Reads the `OIDC_*` variables, single sign-on is disabled when `OIDC_ISSUER` isn't set.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let issuer = var("OIDC_ISSUER")?;
+        if !is_secure(&issuer) {
+            log::warn!(
+                "OIDC_ISSUER {} isn't an https url, logins can be tampered with",
+                issuer
+            );
+        }
+        let client_id = var("OIDC_CLIENT_ID").expect("OIDC_ISSUER is set without OIDC_CLIENT_ID");
+        let redirect_url =
+            var("OIDC_REDIRECT_URL").expect("OIDC_ISSUER is set without OIDC_REDIRECT_URL");
+        Some(Self {
+            issuer: issuer.trim_end_matches('/').to_owned(),
+            client_id,
+            client_secret: var("OIDC_CLIENT_SECRET"),
+            redirect_url,
+            scopes: var("OIDC_SCOPES").unwrap_or_else(|| DEFAULT_SCOPES.into()),
+            require_for_create: var("OIDC_REQUIRE_FOR_CREATE")
+                .is_some_and(|value| value != "0" && value != "false"),
+        })
+    }
+}
+
+/// Whether requests to `url` can't be read on the way, https or plain http to this host.
+fn is_secure(url: &str) -> bool {
+    let Ok(uri) = url.parse::<Uri>() else {
+        return false;
+    };
+    match uri.scheme_str() {
+        Some("https") => true,
+        Some("http") => match uri.host() {
+            Some("localhost") => true,
+            Some(host) => host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<std::net::IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback()),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// The part of the discovery document we use.
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct IdClaims {
+    sub: String,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    preferred_username: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+}
+
+struct PendingLogin {
+    verifier: String,
+    nonce: String,
+    return_to: String,
+    expires: u128,
+}
+
+struct Jwks {
+    keys: JwkSet,
+    fetched: u128,
+}
+
+pub struct Oidc {
+    config: OidcConfig,
+    client: Client<HttpsConnector<HttpConnector>>,
+    discovery: OnceCell<Discovery>,
+    jwks: RwLock<Option<Jwks>>,
+    /// Logins sent to the issuer, by their `state`.
+    logins: scc::HashMap<String, PendingLogin, RandomState>,
+}
+
+impl Oidc {
+    pub fn from_env() -> Option<Self> {
+        OidcConfig::from_env().map(Self::new)
+    }
+
+    pub fn new(config: OidcConfig) -> Self {
+        let connector = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Self {
+            config,
+            client: Client::builder().build(connector),
+            discovery: OnceCell::new(),
+            jwks: RwLock::new(None),
+            logins: Default::default(),
+        }
+    }
+
+    #[inline]
+    pub fn require_for_create(&self) -> bool {
+        self.config.require_for_create
+    }
+
+    async fn send(&self, request: Request<Body>) -> Result<(StatusCode, Vec<u8>), OidcError> {
+        let uri = request.uri().clone();
+        let response = async {
+            let response = self.client.request(request).await?;
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            Ok::<_, hyper::Error>((status, body.to_vec()))
+        };
+        match tokio::time::timeout(PROVIDER_TIMEOUT, response).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(err)) => {
+                log::error!("oidc request to {} failed: {}", uri, err);
+                Err(OidcError::Provider)
+            }
+            Err(_) => {
+                log::error!("oidc request to {} timed out", uri);
+                Err(OidcError::Provider)
+            }
+        }
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, uri: &str) -> Result<T, OidcError> {
+        let request = Request::get(uri)
+            .header(header::ACCEPT, "application/json")
+            .body(Body::empty())
+            .map_err(|err| {
+                log::error!("oidc: bad url {}: {}", uri, err);
+                OidcError::Provider
+            })?;
+        let (status, body) = self.send(request).await?;
+        if !status.is_success() {
+            log::error!("oidc: {} answered {}", uri, status);
+            return Err(OidcError::Provider);
+        }
+        serde_json::from_slice(&body).map_err(|err| {
+            log::error!("oidc: {} sent bad json: {}", uri, err);
+            OidcError::Provider
+        })
+    }
+
+    /// Fetched on the first login, the issuer doesn't have to be up when the server starts.
+    async fn discovery(&self) -> Result<&Discovery, OidcError> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let uri = format!("{}/.well-known/openid-configuration", self.config.issuer);
+                let discovery: Discovery = self.get_json(&uri).await?;
+                if discovery.issuer.trim_end_matches('/') != self.config.issuer {
+                    log::error!(
+                        "oidc: the discovery document is for {}, not {}",
+                        discovery.issuer,
+                        self.config.issuer
+                    );
+                    return Err(OidcError::Provider);
+                }
+                Ok(discovery)
+            })
+            .await
+    }
+
+    /// The key the issuer signed with, the keys are fetched again when they don't have it.
+    async fn decoding_key(
+        &self,
+        discovery: &Discovery,
+        kid: Option<&str>,
+    ) -> Result<DecodingKey, OidcError> {
+        let find = |jwks: &Jwks| {
+            let jwk = match kid {
+                Some(kid) => jwks.keys.find(kid),
+                None if jwks.keys.keys.len() == 1 => jwks.keys.keys.first(),
+                None => None,
+            };
+            jwk.map(|jwk| DecodingKey::from_jwk(jwk).map_err(|_| OidcError::BadIdToken))
+        };
+
+        if let Some(key) = self.jwks.read().await.as_ref().and_then(find) {
+            return key;
+        }
+        let mut jwks = self.jwks.write().await;
+        let fresh = jwks
+            .as_ref()
+            .is_some_and(|jwks| get_elapsed_milis() < jwks.fetched + JWKS_REFRESH);
+        if !fresh {
+            *jwks = Some(Jwks {
+                keys: self.get_json(&discovery.jwks_uri).await?,
+                fetched: get_elapsed_milis(),
+            });
+        }
+        jwks.as_ref()
+            .and_then(find)
+            .unwrap_or(Err(OidcError::BadIdToken))
+    }
+
+    /// Starts a login, returns its `state` and where to send the browser.
+    pub async fn start_login(&self, return_to: String) -> Result<(String, String), OidcError> {
+        let discovery = self.discovery().await?;
+        let state = random_token();
+        let nonce = random_token();
+        let verifier = random_token();
+        let challenge =
+            URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, verifier.as_bytes()));
+
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("scope", &self.config.scopes)
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256")
+            .finish();
+        let separator = if discovery.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        let url = format!("{}{}{}", discovery.authorization_endpoint, separator, query);
+
+        let login = PendingLogin {
+            verifier,
+            nonce,
+            return_to,
+            expires: get_elapsed_milis() + LOGIN_EXPIRATION,
+        };
+        let _ = self.logins.insert_async(state.clone(), login).await;
+        Ok((state, url))
+    }
+
+    /// Ends the login the issuer sent the browser back from, returns who signed in and where to
+    /// send them.
+    pub async fn finish_login(
+        &self,
+        state: &str,
+        code: &str,
+    ) -> Result<(SsoSession, String), OidcError> {
+        let Some((_, login)) = self.logins.remove_async(state).await else {
+            return Err(OidcError::BadLogin);
+        };
+        if get_elapsed_milis() > login.expires {
+            return Err(OidcError::BadLogin);
+        }
+        let discovery = self.discovery().await?;
+        let id_token = self.exchange(discovery, code, &login.verifier).await?;
+        let claims = self.verify(discovery, &id_token, &login.nonce).await?;
+        Ok((SsoSession::new(claims), login.return_to))
+    }
+
+    async fn exchange(
+        &self,
+        discovery: &Discovery,
+        code: &str,
+        verifier: &str,
+    ) -> Result<String, OidcError> {
+        let form = form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "authorization_code")
+            .append_pair("code", code)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("code_verifier", verifier)
+            .finish();
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(&discovery.token_endpoint)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::ACCEPT, "application/json");
+        if let Some(secret) = &self.config.client_secret {
+            if !is_secure(&discovery.token_endpoint) {
+                log::error!(
+                    "oidc: not sending the client secret in cleartext to {}",
+                    discovery.token_endpoint
+                );
+                return Err(OidcError::Provider);
+            }
+            // client_secret_basic, both parts are form encoded first.
+            let encode =
+                |value: &str| form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>();
+            let credentials = format!("{}:{}", encode(&self.config.client_id), encode(secret));
+            request = request.header(
+                header::AUTHORIZATION,
+                format!("Basic {}", STANDARD.encode(credentials)),
+            );
+        }
+        let request = request.body(Body::from(form)).map_err(|err| {
+            log::error!(
+                "oidc: bad token endpoint {}: {}",
+                discovery.token_endpoint,
+                err
+            );
+            OidcError::Provider
+        })?;
+
+        let (status, body) = self.send(request).await?;
+        if status.is_client_error() {
+            log::warn!(
+                "oidc: the code was refused: {}",
+                String::from_utf8_lossy(&body)
+            );
+            return Err(OidcError::Denied);
+        }
+        if !status.is_success() {
+            log::error!("oidc: the token endpoint answered {}", status);
+            return Err(OidcError::Provider);
+        }
+        let response: TokenResponse = serde_json::from_slice(&body).map_err(|err| {
+            log::error!("oidc: the token endpoint sent bad json: {}", err);
+            OidcError::Provider
+        })?;
+        Ok(response.id_token)
+    }
+
+    async fn verify(
+        &self,
+        discovery: &Discovery,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<IdClaims, OidcError> {
+        let bad_token = |err: jsonwebtoken::errors::Error| {
+            log::warn!("oidc: invalid id token: {}", err);
+            OidcError::BadIdToken
+        };
+        let header = jsonwebtoken::decode_header(id_token).map_err(bad_token)?;
+        if !ID_TOKEN_ALGORITHMS.contains(&header.alg) {
+            log::warn!("oidc: id token signed with {:?}", header.alg);
+            return Err(OidcError::BadIdToken);
+        }
+        let key = self.decoding_key(discovery, header.kid.as_deref()).await?;
+
+        let mut validation = jsonwebtoken::Validation::new(header.alg);
+        validation.set_issuer(&[&discovery.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = jsonwebtoken::decode::<IdClaims>(id_token, &key, &validation)
+            .map_err(bad_token)?
+            .claims;
+        if claims.nonce.as_deref() != Some(nonce) {
+            log::warn!("oidc: id token with the wrong nonce");
+            return Err(OidcError::BadIdToken);
+        }
+        Ok(claims)
+    }
+
+    /// Forgets the logins which were never finished.
+    pub async fn prune(&self) {
+        let now = get_elapsed_milis();
+        self.logins
+            .retain_async(|_, login| login.expires > now)
+            .await;
+    }
+}
+
+/// Claims of the `sso` cookie.
+#[derive(Serialize, Deserialize)]
+pub struct SsoSession {
+    /// The subject at the issuer.
+    pub user: String,
+    pub name: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    sub: String,
+    company: String,
+    pub exp: u128,
+}
+
+impl SsoSession {
+    fn new(claims: IdClaims) -> Self {
+        let name = claims
+            .name
+            .or(claims.preferred_username)
+            .or_else(|| claims.email.clone())
+            .unwrap_or_else(|| claims.sub.clone());
+        Self {
+            user: claims.sub,
+            name,
+            email: claims.email,
+            sub: SSO_SUB.into(),
+            company: COMPANY.into(),
+            exp: get_elapsed_milis() + SSO_EXPIRATION,
+        }
+    }
+
+    pub fn from_token(token: &str, keys: &Keys) -> Result<Self, jsonwebtoken::errors::Error> {
+        let mut validation = jsonwebtoken::Validation::default();
+        validation.sub = Some(SSO_SUB.into());
+        let session = jsonwebtoken::decode::<SsoSession>(token, &keys.decoding, &validation)?;
+        Ok(session.claims)
+    }
+
+    #[inline]
+    pub fn encode(&self, keys: &Keys) -> String {
+        jsonwebtoken::encode(&jsonwebtoken::Header::default(), &self, &keys.encoding)
+            .expect("Shouldn't fail")
+    }
+
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        get_elapsed_milis() <= self.exp
+    }
+}
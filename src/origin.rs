@@ -0,0 +1,118 @@
+//! Which sites may make state changing requests, and open WebSockets, with our cookies.
+//!
+//! Browsers attach cookies to requests made by any site, the `Origin` they send says which one
+//! made them. `ALLOWED_ORIGINS` lists the origins of the deployment, comma separated like
+//! `https://spoon.example.com`. Without it only the origin matching the `Host` of the request and
+//! the scheme the client used is allowed, which is wrong behind proxies rewriting `Host`. Proxies
+//! terminating TLS have to be in `TRUSTED_PROXIES` for the scheme to be https. Requests without
+//! an `Origin` don't come from a page and go through, the `SameSite` cookies cover browsers
+//! leaving it out.
+
+use axum::extract::State;
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use internal_server_error::InternalServerError;
+use thiserror::Error;
+
+use crate::client_addr::{ClientAddr, ClientScheme};
+
+#[derive(Debug, Error, InternalServerError)]
+pub enum OriginError {
+    #[error("Requests from this origin aren't allowed.")]
+    #[code(StatusCode::FORBIDDEN)]
+    Forbidden,
+}
+
+#[derive(Default)]
+pub struct AllowedOrigins {
+    origins: Vec<String>,
+}
+
+fn normalize_origin(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_ascii_lowercase()
+}
+
+impl AllowedOrigins {
+    pub fn from_env() -> Self {
+        let Ok(origins) = std::env::var("ALLOWED_ORIGINS") else {
+            return Self::default();
+        };
+        let origins = origins
+            .split(',')
+            .map(normalize_origin)
+            .filter(|origin| !origin.is_empty())
+            .filter(|origin| {
+                let valid = origin.starts_with("http://") || origin.starts_with("https://");
+                if !valid {
+                    log::warn!("ALLOWED_ORIGINS: {:?} isn't an http(s) origin", origin);
+                }
+                valid
+            })
+            .collect();
+        Self { origins }
+    }
+
+    /// Whether a page from `origin` may talk to us, `host` being the `Host` of its request and
+    /// `scheme` the one the client used.
+    pub fn is_allowed(&self, origin: &str, host: Option<&str>, scheme: &str) -> bool {
+        let origin = normalize_origin(origin);
+        if !self.origins.is_empty() {
+            return self.origins.contains(&origin);
+        }
+        // Opaque origins ("null") have no host, they never match.
+        origin
+            .split_once("://")
+            .zip(host)
+            .is_some_and(|((origin_scheme, authority), host)| {
+                origin_scheme == scheme && authority.eq_ignore_ascii_case(host.trim())
+            })
+    }
+}
+
+fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::UPGRADE)
+        .and_then(|upgrade| upgrade.to_str().ok())
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+}
+
+/// Middleware refusing the WebSocket upgrades and the requests which aren't `GET`, `HEAD` or
+/// `OPTIONS` coming from pages of other origins.
+pub async fn check<B>(
+    State(origins): State<&'static AllowedOrigins>,
+    ClientAddr(addr): ClientAddr,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    let headers = request.headers();
+    if !safe || is_websocket_upgrade(headers) {
+        if let Some(origin) = headers.get(header::ORIGIN) {
+            let host = headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok());
+            let scheme = request
+                .extensions()
+                .get::<ClientScheme>()
+                .map_or("http", |ClientScheme(scheme)| scheme);
+            let allowed = origin
+                .to_str()
+                .is_ok_and(|origin| origins.is_allowed(origin, host, scheme));
+            if !allowed {
+                log::warn!(
+                    "peer={} {} {} refused from origin {:?}",
+                    addr,
+                    request.method(),
+                    request.uri().path(),
+                    origin
+                );
+                return OriginError::Forbidden.into_response();
+            }
+        }
+    }
+    next.run(request).await
+}
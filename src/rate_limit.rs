@@ -0,0 +1,179 @@
+//! Token buckets keeping a single address from hammering the server, one bucket per IP and
+//! limiter.
+//!
+//! Every quota is read from the environment as `<count>/<seconds>`, a bucket holds up to `count`
+//! tokens and refills all of them in `seconds`. `0` or `off` disables the limiter.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use ahash::RandomState;
+use axum::extract::State;
+use axum::http::{header::RETRY_AFTER, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use scc::HashMap;
+use tokio::time::Instant;
+
+use crate::client_addr::ClientAddr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    burst: u32,
+    period: Duration,
+}
+
+impl Quota {
+    pub const fn new(burst: u32, period: Duration) -> Self {
+        Self { burst, period }
+    }
+
+    fn parse(quota: &str) -> Option<Option<Self>> {
+        let quota = quota.trim();
+        if quota == "0" || quota.eq_ignore_ascii_case("off") {
+            return Some(None);
+        }
+        let (burst, secs) = quota.split_once('/')?;
+        let burst: u32 = burst.trim().parse().ok()?;
+        let secs: f64 = secs.trim().parse().ok()?;
+        if burst == 0 || !secs.is_finite() || secs <= 0.0 {
+            return None;
+        }
+        Some(Some(Self::new(burst, Duration::from_secs_f64(secs))))
+    }
+
+    /// Reads the quota from `var`, falling back to `default` when it's unset or malformed.
+    pub fn from_env(var: &str, default: Quota) -> Option<Self> {
+        let Ok(value) = std::env::var(var) else {
+            return Some(default);
+        };
+        Self::parse(&value).unwrap_or_else(|| {
+            log::warn!("{}={:?} isn't a valid quota, using the default", var, value);
+            Some(default)
+        })
+    }
+
+    fn tokens_per_sec(&self) -> f64 {
+        f64::from(self.burst) / self.period.as_secs_f64()
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    quota: Option<Quota>,
+    buckets: HashMap<IpAddr, Bucket, RandomState>,
+}
+
+impl RateLimiter {
+    pub fn new(quota: Option<Quota>) -> Self {
+        Self {
+            quota,
+            buckets: HashMap::with_hasher(RandomState::new()),
+        }
+    }
+
+    /// Takes a token from the bucket of `ip`, or tells how long until the next one.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let Some(quota) = self.quota else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut entry = self.buckets.entry(ip).or_insert_with(|| Bucket {
+            tokens: f64::from(quota.burst),
+            updated: now,
+        });
+        let bucket = entry.get_mut();
+        let refilled = (now - bucket.updated).as_secs_f64() * quota.tokens_per_sec();
+        bucket.tokens = (bucket.tokens + refilled).min(f64::from(quota.burst));
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / quota.tokens_per_sec(),
+            ))
+        }
+    }
+
+    /// Forgets the addresses which had the time to refill their whole bucket.
+    pub async fn prune(&self) {
+        let Some(quota) = self.quota else {
+            return;
+        };
+        let now = Instant::now();
+        self.buckets
+            .retain_async(|_, bucket| now - bucket.updated < quota.period)
+            .await;
+    }
+}
+
+/// Every limiter of the server, configured from the environment.
+pub struct RateLimits {
+    /// Any request to the REST API, from `RATE_LIMIT_HTTP`.
+    pub http: RateLimiter,
+    /// Room creations, on top of `http`, from `RATE_LIMIT_ROOM_CREATE`.
+    pub room_create: RateLimiter,
+    /// Logins and registrations, on top of `http`, from `RATE_LIMIT_LOGIN`.
+    pub login: RateLimiter,
+    /// WebSocket upgrades, from `RATE_LIMIT_WS_CONNECT`.
+    pub ws_connect: RateLimiter,
+    /// Text packets sent over every WebSocket of an address, from `RATE_LIMIT_WS_PACKETS`.
+    pub ws_packets: RateLimiter,
+}
+
+impl RateLimits {
+    pub fn from_env() -> Self {
+        let minute = Duration::from_secs(60);
+        Self {
+            http: RateLimiter::new(Quota::from_env("RATE_LIMIT_HTTP", Quota::new(120, minute))),
+            room_create: RateLimiter::new(Quota::from_env(
+                "RATE_LIMIT_ROOM_CREATE",
+                Quota::new(10, minute),
+            )),
+            login: RateLimiter::new(Quota::from_env("RATE_LIMIT_LOGIN", Quota::new(10, minute))),
+            ws_connect: RateLimiter::new(Quota::from_env(
+                "RATE_LIMIT_WS_CONNECT",
+                Quota::new(30, minute),
+            )),
+            ws_packets: RateLimiter::new(Quota::from_env(
+                "RATE_LIMIT_WS_PACKETS",
+                Quota::new(20, Duration::from_secs(1)),
+            )),
+        }
+    }
+
+    pub async fn prune(&self) {
+        self.http.prune().await;
+        self.room_create.prune().await;
+        self.login.prune().await;
+        self.ws_connect.prune().await;
+        self.ws_packets.prune().await;
+    }
+}
+
+/// Middleware answering `429 Too Many Requests` once the address ran out of tokens.
+pub async fn limit<B>(
+    State(limiter): State<&'static RateLimiter>,
+    ClientAddr(addr): ClientAddr,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match limiter.check(addr.ip()) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            log::debug!("peer={} rate limited on {}", addr, request.uri().path());
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, secs.to_string())],
+                "Too many requests, slow down.",
+            )
+                .into_response()
+        }
+    }
+}
@@ -1,7 +1,17 @@
 use axum::extract::FromRef;
 use std::{collections::HashMap, path::PathBuf};
+use tower_cookies::Cookies;
 
-use crate::{basic_auth::CHECKED_AUTH_EXPIRATION, ws_handler::ws_state::WsState};
+use crate::{
+    accounts::{Account, Accounts, SESSION_COOKIE},
+    basic_auth::CHECKED_AUTH_EXPIRATION,
+    client_addr::TrustedProxies,
+    oidc::{Oidc, SsoSession, SSO_COOKIE},
+    origin::AllowedOrigins,
+    rate_limit::RateLimits,
+    web::templates::Templates,
+    ws_handler::{ws_state::WsState, DEFAULT_MAX_WRITE_BUFFER},
+};
 
 pub const PUBLIC_DIR: &str = "public";
 
@@ -15,7 +25,33 @@ pub enum WebPageFileType {
 #[derive(Clone, FromRef)]
 pub struct ServerState {
     pub ws_state: &'static WsState,
+    /// Token guarding the admin API, the admin API is disabled when `ADMIN_TOKEN` isn't set.
+    pub admin_token: Option<&'static str>,
+    /// Token guarding `/metrics`, from `METRICS_TOKEN` or else the admin token. The metrics are
+    /// disabled without either.
+    #[from_ref(skip)]
+    pub metrics_token: Option<&'static str>,
+    /// Cap of the bytes buffered for a slow WebSocket, from `WS_MAX_WRITE_BUFFER`.
+    pub max_write_buffer: usize,
     web_dirs: &'static HashMap<WebPageFileType, PathBuf>,
+    /// Compiled from the dynamic web directory.
+    pub(crate) templates: &'static Templates,
+    /// Quotas per address, from the `RATE_LIMIT_*` variables.
+    pub(crate) rate_limits: &'static RateLimits,
+    /// Reverse proxies whose forwarding headers are believed, from `TRUSTED_PROXIES`.
+    pub(crate) trusted_proxies: &'static TrustedProxies,
+    /// Origins whose pages may use our cookies, from `ALLOWED_ORIGINS`.
+    pub(crate) allowed_origins: &'static AllowedOrigins,
+    /// Local accounts, only when `ACCOUNTS_DB` is set.
+    pub(crate) accounts: Option<&'static Accounts>,
+    /// Single sign-on, only when `OIDC_ISSUER` is set.
+    pub(crate) oidc: Option<&'static Oidc>,
+}
+
+impl Default for ServerState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ServerState {
@@ -29,23 +65,88 @@ impl ServerState {
         let js_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
             .join(PUBLIC_DIR)
             .join("js");
+        let templates = Box::leak(Box::new(Templates::new(&dyn_dir)));
         let web_dirs = Box::leak(Box::new(HashMap::from_iter([
             (WebPageFileType::Static, statics_dir),
             (WebPageFileType::Dynamic, dyn_dir),
             (WebPageFileType::JS, js_dir),
         ])));
 
-        let ws_state = Box::leak(Box::new(WsState::default()));
-        tokio::spawn(async {
+        let token_var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|token| !token.is_empty())
+                .map(|token| &*Box::leak(token.into_boxed_str()))
+        };
+        let admin_token = token_var("ADMIN_TOKEN");
+        let metrics_token = token_var("METRICS_TOKEN").or(admin_token);
+
+        let max_write_buffer = std::env::var("WS_MAX_WRITE_BUFFER")
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .unwrap_or(DEFAULT_MAX_WRITE_BUFFER);
+
+        let accounts = Accounts::from_env().map(|accounts| &*Box::leak(Box::new(accounts)));
+        let oidc = Oidc::from_env().map(|oidc| &*Box::leak(Box::new(oidc)));
+        let trusted_proxies = Box::leak(Box::new(TrustedProxies::from_env()));
+        let allowed_origins = Box::leak(Box::new(AllowedOrigins::from_env()));
+        let rate_limits = &*Box::leak(Box::new(RateLimits::from_env()));
+        let ws_state = &*Box::leak(Box::new(WsState::default()));
+        tokio::spawn(async move {
             loop {
                 ws_state.update_checked_auths().await;
+                rate_limits.prune().await;
+                if let Some(oidc) = oidc {
+                    oidc.prune().await;
+                }
                 tokio::time::sleep(std::time::Duration::from_millis(
                     CHECKED_AUTH_EXPIRATION as u64,
                 ))
                 .await;
             }
         });
-        Self { ws_state, web_dirs }
+        Self {
+            ws_state,
+            admin_token,
+            metrics_token,
+            max_write_buffer,
+            web_dirs,
+            templates,
+            rate_limits,
+            trusted_proxies,
+            allowed_origins,
+            accounts,
+            oidc,
+        }
+    }
+
+    /// The account the browser is logged in with, if any.
+    pub(crate) async fn session_account(&self, cookies: &Cookies) -> Option<Account> {
+        let accounts = self.accounts?;
+        let token = cookies.get(SESSION_COOKIE)?.value().to_owned();
+        let keys = &self.ws_state.keys;
+        // The store can wait for the disk, keep it off the async threads.
+        tokio::task::spawn_blocking(move || accounts.session_account(&token, keys))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// The normalized username of the browser's account, banned accounts don't count.
+    pub(crate) async fn session_account_key(&self, cookies: &Cookies) -> Option<String> {
+        self.session_account(cookies)
+            .await
+            .filter(|account| !account.banned)
+            .map(|account| account.key())
+    }
+
+    /// Who the browser signed in as through single sign-on, if it did.
+    pub(crate) fn sso_session(&self, cookies: &Cookies) -> Option<SsoSession> {
+        self.oidc?;
+        let cookie = cookies.get(SSO_COOKIE)?;
+        SsoSession::from_token(cookie.value(), &self.ws_state.keys)
+            .ok()
+            .filter(SsoSession::is_valid)
     }
 
     pub fn get_static_dir(&self) -> &PathBuf {
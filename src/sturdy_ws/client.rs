@@ -0,0 +1,302 @@
+//! Client side of the WebSocket handshake, for bots, load tests and integration tests talking
+//! to a running server.
+//!
+//! Only plain `ws://` urls are supported, there is no TLS.
+//!
+//! ```no_run
+//! use futures_util::{SinkExt, StreamExt};
+//! use sturdy_spoon::sturdy_ws::{client::WebSocketConnector, Message};
+//!
+//! # async fn run() -> Result<(), sturdy_spoon::sturdy_ws::Error> {
+//! let (mut socket, _response) = WebSocketConnector::new("ws://127.0.0.1:3000/ws/abc")?
+//!     .connect()
+//!     .await?;
+//! socket.send(Message::Text("hello".into())).await?;
+//! while let Some(msg) = socket.next().await {
+//!     println!("{:?}", msg?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Response, StatusCode, Uri,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::sturdy_tungstenite::{
+    error::{CapacityError, Error as WsError, ProtocolError, UrlError},
+    protocol::{Role, WebSocketConfig},
+};
+use super::ws::sign;
+use super::ws_stream::WebSocketStream;
+
+/// The handshake response, headers included, can't be bigger than this.
+const MAX_RESPONSE_LEN: usize = 8 * 1024;
+
+/// The handshake response can't have more headers than this.
+const MAX_RESPONSE_HEADERS: usize = 64;
+
+const READ_CHUNK_LEN: usize = 1024;
+
+/// Builder for a client WebSocket connection.
+#[derive(Debug, Clone)]
+pub struct WebSocketConnector {
+    uri: Uri,
+    protocols: Vec<String>,
+    headers: HeaderMap,
+    config: WebSocketConfig,
+}
+
+impl WebSocketConnector {
+    /// Prepares a connection to a `ws://` url.
+    pub fn new(url: &str) -> Result<Self, WsError> {
+        let uri: Uri = url.parse().map_err(|_| UrlError::NoHostName)?;
+        match uri.scheme_str() {
+            Some("ws") => {}
+            Some("wss") => return Err(UrlError::TlsFeatureNotEnabled.into()),
+            _ => return Err(UrlError::UnsupportedUrlScheme.into()),
+        }
+        match uri.host() {
+            None => return Err(UrlError::NoHostName.into()),
+            Some("") => return Err(UrlError::EmptyHostName.into()),
+            Some(_) => {}
+        }
+        Ok(Self {
+            uri,
+            protocols: Vec::new(),
+            headers: HeaderMap::new(),
+            config: WebSocketConfig::default(),
+        })
+    }
+
+    /// Offer these subprotocols in decreasing order of preference.
+    ///
+    /// When any is offered, the server has to select one of them.
+    pub fn protocols<I>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.protocols = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Add a header to the upgrade request, like a `Cookie` or a `User-Agent`.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Set the maximum write buffer size (defaults to usize::MAX)
+    pub fn max_write_buffer_size(mut self, max: usize) -> Self {
+        self.config.max_write_buffer_size = max;
+        self
+    }
+
+    /// Set the maximum message size (defaults to 64 megabytes)
+    pub fn max_message_size(mut self, max: usize) -> Self {
+        self.config.max_message_size = Some(max);
+        self
+    }
+
+    /// Set the maximum frame size (defaults to 16 megabytes)
+    pub fn max_frame_size(mut self, max: usize) -> Self {
+        self.config.max_frame_size = Some(max);
+        self
+    }
+
+    /// Opens a TCP connection to the url and performs the handshake over it.
+    pub async fn connect(self) -> Result<(WebSocketStream<TcpStream>, Response<()>), WsError> {
+        // Ipv6 hosts keep their brackets in urls.
+        let host = self.uri.host().unwrap_or_default();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = self.uri.port_u16().unwrap_or(80);
+        let stream = TcpStream::connect((host, port))
+            .await
+            .map_err(|_| UrlError::UnableToConnect(self.uri.to_string()))?;
+        stream.set_nodelay(true)?;
+        self.connect_on(stream).await
+    }
+
+    /// Performs the handshake over an already opened stream.
+    pub async fn connect_on<S>(
+        self,
+        mut stream: S,
+    ) -> Result<(WebSocketStream<S>, Response<()>), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let key = generate_key();
+        stream.write_all(&self.request_bytes(&key)).await?;
+        stream.flush().await?;
+
+        let (response, rest) = read_response(&mut stream).await?;
+        self.verify_response(&response, &key)?;
+
+        let socket =
+            WebSocketStream::from_partially_read(stream, rest, Role::Client, Some(self.config))
+                .await;
+        Ok((socket, response))
+    }
+
+    fn request_bytes(&self, key: &str) -> Vec<u8> {
+        let path = self
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .filter(|path| !path.is_empty())
+            .unwrap_or("/");
+        // `new` made sure there is a host.
+        let host = self.uri.authority().map(|a| a.as_str()).unwrap_or_default();
+
+        let mut request = format!(
+            "GET {path} HTTP/1.1\r\n\
+             Host: {host}\r\n\
+             Connection: Upgrade\r\n\
+             Upgrade: websocket\r\n\
+             Sec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Key: {key}\r\n"
+        )
+        .into_bytes();
+        if !self.protocols.is_empty() {
+            request.extend_from_slice(b"Sec-WebSocket-Protocol: ");
+            request.extend_from_slice(self.protocols.join(", ").as_bytes());
+            request.extend_from_slice(b"\r\n");
+        }
+        for (name, value) in &self.headers {
+            request.extend_from_slice(name.as_str().as_bytes());
+            request.extend_from_slice(b": ");
+            request.extend_from_slice(value.as_bytes());
+            request.extend_from_slice(b"\r\n");
+        }
+        request.extend_from_slice(b"\r\n");
+        request
+    }
+
+    fn verify_response(&self, response: &Response<()>, key: &str) -> Result<(), WsError> {
+        if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+            return Err(WsError::Http(response.status()));
+        }
+        let headers = response.headers();
+
+        let upgrade = headers
+            .get(header::UPGRADE)
+            .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"websocket"));
+        if !upgrade {
+            return Err(ProtocolError::MissingUpgradeWebSocketHeader.into());
+        }
+
+        let connection = headers
+            .get(header::CONNECTION)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| {
+                value
+                    .split(',')
+                    .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+            });
+        if !connection {
+            return Err(ProtocolError::MissingConnectionUpgradeHeader.into());
+        }
+
+        if headers.get(header::SEC_WEBSOCKET_ACCEPT) != Some(&sign(key.as_bytes())) {
+            return Err(ProtocolError::SecWebSocketAcceptKeyMismatch.into());
+        }
+
+        match headers
+            .get(header::SEC_WEBSOCKET_PROTOCOL)
+            .map(|value| value.to_str())
+        {
+            None if self.protocols.is_empty() => {}
+            Some(Ok(protocol)) if self.protocols.iter().any(|p| p == protocol) => {}
+            _ => return Err(ProtocolError::InvalidSubProtocol.into()),
+        }
+
+        // No extension is ever offered.
+        if headers.contains_key(header::SEC_WEBSOCKET_EXTENSIONS) {
+            return Err(ProtocolError::InvalidExtension.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Connects to a `ws://` url with the default settings.
+pub async fn connect(url: &str) -> Result<(WebSocketStream<TcpStream>, Response<()>), WsError> {
+    WebSocketConnector::new(url)?.connect().await
+}
+
+fn generate_key() -> String {
+    use base64::engine::Engine as _;
+
+    let nonce: [u8; 16] = rand::random();
+    base64::engine::general_purpose::STANDARD.encode(nonce)
+}
+
+/// Reads the handshake response, returning it with the bytes read past its end, which are
+/// already part of the WebSocket stream.
+async fn read_response<S>(stream: &mut S) -> Result<(Response<()>, Vec<u8>), WsError>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf = Vec::with_capacity(READ_CHUNK_LEN);
+    let end = loop {
+        let searched = buf.len().saturating_sub(3);
+        let len = buf.len();
+        buf.resize(len + READ_CHUNK_LEN, 0);
+        let read = stream.read(&mut buf[len..]).await?;
+        buf.truncate(len + read);
+        if read == 0 {
+            return Err(ProtocolError::HandshakeIncomplete.into());
+        }
+        if let Some(pos) = buf[searched..].windows(4).position(|w| w == b"\r\n\r\n") {
+            break searched + pos + 4;
+        }
+        if buf.len() > MAX_RESPONSE_LEN {
+            return Err(CapacityError::HeaderTooLong.into());
+        }
+    };
+    if end > MAX_RESPONSE_LEN {
+        return Err(CapacityError::HeaderTooLong.into());
+    }
+    let rest = buf.split_off(end);
+    Ok((parse_response(&buf)?, rest))
+}
+
+fn parse_response(head: &[u8]) -> Result<Response<()>, WsError> {
+    let head = std::str::from_utf8(head).map_err(|_| ProtocolError::InvalidHttpResponse)?;
+    let mut lines = head.split("\r\n").filter(|line| !line.is_empty());
+
+    let status_line = lines.next().ok_or(ProtocolError::InvalidHttpResponse)?;
+    let mut parts = status_line.splitn(3, ' ');
+    match parts.next() {
+        Some("HTTP/1.1") => {}
+        Some(version) if version.starts_with("HTTP/") => {
+            return Err(ProtocolError::WrongHttpVersion.into())
+        }
+        _ => return Err(ProtocolError::InvalidHttpResponse.into()),
+    }
+    let status = parts
+        .next()
+        .and_then(|code| StatusCode::from_bytes(code.as_bytes()).ok())
+        .ok_or(ProtocolError::InvalidHttpResponse)?;
+
+    let mut response = Response::new(());
+    *response.status_mut() = status;
+    for (count, line) in lines.enumerate() {
+        if count >= MAX_RESPONSE_HEADERS {
+            return Err(CapacityError::TooManyHeaders.into());
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(ProtocolError::InvalidHttpResponse)?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| ProtocolError::InvalidHttpResponse)?;
+        let value =
+            HeaderValue::from_str(value.trim()).map_err(|_| ProtocolError::InvalidHttpResponse)?;
+        response.headers_mut().append(name, value);
+    }
+    Ok(response)
+}
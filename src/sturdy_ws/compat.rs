@@ -50,6 +50,7 @@ pub(crate) struct AllowStd<S> {
 // read waker slot for this, but any would do.
 //
 // Don't ever use this from multiple tasks at the same time!
+#[allow(dead_code)]
 pub(crate) trait SetWaker {
     fn set_waker(&self, waker: &task::Waker);
 }
@@ -1,4 +1,6 @@
+pub mod client;
 mod compat;
+pub mod stats;
 pub mod sturdy_tungstenite;
 mod ws;
 mod ws_message;
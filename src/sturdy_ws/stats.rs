@@ -0,0 +1,38 @@
+//! Process wide WebSocket frame counters, cheap enough to always be on.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static FRAMES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static FRAMES_SENT: AtomicU64 = AtomicU64::new(0);
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+
+/// A snapshot of the frame counters, the bytes include the frame headers.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    pub frames_received: u64,
+    pub bytes_received: u64,
+    pub frames_sent: u64,
+    pub bytes_sent: u64,
+}
+
+#[inline]
+pub(crate) fn record_received(bytes: usize) {
+    FRAMES_RECEIVED.fetch_add(1, Ordering::Relaxed);
+    BYTES_RECEIVED.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+#[inline]
+pub(crate) fn record_sent(bytes: usize) {
+    FRAMES_SENT.fetch_add(1, Ordering::Relaxed);
+    BYTES_SENT.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+pub fn frame_stats() -> FrameStats {
+    FrameStats {
+        frames_received: FRAMES_RECEIVED.load(Ordering::Relaxed),
+        bytes_received: BYTES_RECEIVED.load(Ordering::Relaxed),
+        frames_sent: FRAMES_SENT.load(Ordering::Relaxed),
+        bytes_sent: BYTES_SENT.load(Ordering::Relaxed),
+    }
+}
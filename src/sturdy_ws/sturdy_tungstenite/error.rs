@@ -57,6 +57,9 @@ pub enum Error {
     /// Invalid URL.
     #[error("URL error: {0}")]
     Url(#[from] UrlError),
+    /// The server answered the handshake with something else than `101 Switching Protocols`.
+    #[error("HTTP error: {0}")]
+    Http(http::StatusCode),
 }
 
 impl From<str::Utf8Error> for Error {
@@ -78,6 +81,8 @@ pub enum CapacityError {
     #[error("Too many headers")]
     TooManyHeaders,
     /// Received header is too long.
+    #[error("Header too long")]
+    HeaderTooLong,
     /// Message is bigger than the maximum allowed size.
     #[error("Message too long: {size} > {max_size}")]
     MessageTooLong {
@@ -119,6 +124,15 @@ pub enum ProtocolError {
     /// Custom responses must be unsuccessful.
     #[error("Custom response must not be successful")]
     CustomResponseSuccessful,
+    /// The handshake response has a malformed status line or header.
+    #[error("Invalid HTTP response")]
+    InvalidHttpResponse,
+    /// The server picked a subprotocol the client didn't offer, or none when one was required.
+    #[error("Server selected an invalid subprotocol")]
+    InvalidSubProtocol,
+    /// The server accepted an extension the client didn't offer.
+    #[error("Server accepted an extension that wasn't offered")]
+    InvalidExtension,
     /// No more data while still performing handshake.
     #[error("Handshake not finished")]
     HandshakeIncomplete,
@@ -131,6 +145,9 @@ pub enum ProtocolError {
     /// Reserved bits in frame header are non-zero.
     #[error("Reserved bits are non-zero")]
     NonZeroReservedBits,
+    /// A compressed message couldn't be decompressed.
+    #[error("Invalid compressed data")]
+    InvalidCompressedData,
     /// The server must close the connection when an unmasked frame is received.
     #[error("Received an unmasked frame from client")]
     UnmaskedFrameFromClient,
@@ -0,0 +1,195 @@
+//! permessage-deflate extension (RFC 7692).
+//!
+//! Outgoing messages are always compressed without context takeover, so the same compressed
+//! bytes can be shared by every connection, incoming messages keep the peer's context unless
+//! it agreed not to.
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+use super::error::{CapacityError, Error, ProtocolError, Result};
+use super::Role;
+
+/// Name of the extension in `Sec-WebSocket-Extensions`.
+pub const PERMESSAGE_DEFLATE: &str = "permessage-deflate";
+
+/// Messages smaller than this are sent uncompressed, it isn't worth it.
+pub const MIN_COMPRESS_LEN: usize = 256;
+
+/// Every message ends with an empty stored block when flushed, RFC 7692 removes it.
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// The only window size the deflate backend supports.
+const MAX_WINDOW_BITS: u8 = 15;
+
+/// Negotiated permessage-deflate parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeflateConfig {
+    /// The server compresses each message on its own.
+    pub server_no_context_takeover: bool,
+    /// The client compresses each message on its own.
+    pub client_no_context_takeover: bool,
+}
+
+impl DeflateConfig {
+    /// Picks the first acceptable permessage-deflate offer from the `Sec-WebSocket-Extensions`
+    /// header values of a client.
+    ///
+    /// `server_no_context_takeover` is always accepted since our compressed messages are shared.
+    pub fn negotiate<'a>(header_values: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        header_values
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .find_map(|offer| {
+                let mut params = offer.split(';').map(str::trim);
+                if params.next()? != PERMESSAGE_DEFLATE {
+                    return None;
+                }
+                Self::accept_offer(params)
+            })
+    }
+
+    fn accept_offer<'a>(params: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let mut config = Self {
+            server_no_context_takeover: true,
+            client_no_context_takeover: false,
+        };
+        let mut seen: Vec<&str> = Vec::new();
+        for param in params {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            };
+            // A parameter can't be repeated in an offer.
+            if seen.contains(&name) {
+                return None;
+            }
+            seen.push(name);
+            match (name, value) {
+                ("server_no_context_takeover", None) => {}
+                ("client_no_context_takeover", None) => config.client_no_context_takeover = true,
+                // We don't have to use a smaller window when the client doesn't need it.
+                ("client_max_window_bits", None) => {}
+                ("client_max_window_bits", Some(bits)) => {
+                    parse_window_bits(bits)?;
+                }
+                // A smaller window can't be honored by our compressor.
+                ("server_max_window_bits", Some(bits)) => {
+                    if parse_window_bits(bits)? != MAX_WINDOW_BITS {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(config)
+    }
+
+    /// The `Sec-WebSocket-Extensions` value accepting these parameters.
+    pub fn response_header(&self) -> String {
+        let mut value = String::from(PERMESSAGE_DEFLATE);
+        if self.server_no_context_takeover {
+            value.push_str("; server_no_context_takeover");
+        }
+        if self.client_no_context_takeover {
+            value.push_str("; client_no_context_takeover");
+        }
+        value
+    }
+}
+
+fn parse_window_bits(bits: &str) -> Option<u8> {
+    // No leading zeros are allowed.
+    if bits.starts_with('0') {
+        return None;
+    }
+    bits.parse()
+        .ok()
+        .filter(|bits| (8..=MAX_WINDOW_BITS).contains(bits))
+}
+
+/// Compresses a whole message payload on its own, without any context takeover.
+pub fn deflate_message(data: &[u8]) -> Vec<u8> {
+    let mut compress = Compress::new(Compression::default(), false);
+    let mut output = Vec::with_capacity(data.len() / 2 + 16);
+    loop {
+        let consumed = compress.total_in() as usize;
+        // Only fails on a misuse of the stream.
+        compress
+            .compress_vec(&data[consumed..], &mut output, FlushCompress::Sync)
+            .expect("Bug: deflate failed");
+        if compress.total_in() as usize == data.len() && output.ends_with(&DEFLATE_TAIL) {
+            break;
+        }
+        output.reserve(output.capacity().max(64));
+    }
+    output.truncate(output.len() - DEFLATE_TAIL.len());
+    output
+}
+
+/// Per connection permessage-deflate state.
+#[derive(Debug)]
+pub(crate) struct DeflateContext {
+    inflate: Decompress,
+    /// Whether the peer compresses each message on its own.
+    peer_no_context_takeover: bool,
+}
+
+impl DeflateContext {
+    pub(crate) fn new(config: DeflateConfig, role: Role) -> Self {
+        let peer_no_context_takeover = match role {
+            Role::Server => config.client_no_context_takeover,
+            Role::Client => config.server_no_context_takeover,
+        };
+        Self {
+            inflate: Decompress::new(false),
+            peer_no_context_takeover,
+        }
+    }
+
+    /// Decompresses a whole message payload, failing once the output goes over `max_size`.
+    pub(crate) fn inflate_message(
+        &mut self,
+        mut data: Vec<u8>,
+        max_size: Option<usize>,
+    ) -> Result<Vec<u8>> {
+        let max_size = max_size.unwrap_or(usize::MAX);
+        data.extend_from_slice(&DEFLATE_TAIL);
+
+        let start_in = self.inflate.total_in();
+        let mut output = Vec::with_capacity(data.len().saturating_mul(2).min(max_size));
+        loop {
+            let consumed = (self.inflate.total_in() - start_in) as usize;
+            if output.len() == output.capacity() {
+                if output.len() >= max_size {
+                    return Err(Error::Capacity(CapacityError::MessageTooLong {
+                        size: output.len().saturating_add(1),
+                        max_size,
+                    }));
+                }
+                let additional = output.capacity().max(256).min(max_size - output.len());
+                output.reserve_exact(additional);
+            }
+            let status = self
+                .inflate
+                .decompress_vec(&data[consumed..], &mut output, FlushDecompress::Sync)
+                .map_err(|_| Error::Protocol(ProtocolError::InvalidCompressedData))?;
+            let consumed = (self.inflate.total_in() - start_in) as usize;
+            match status {
+                // The peer isn't allowed to end the stream.
+                Status::StreamEnd => {
+                    return Err(Error::Protocol(ProtocolError::InvalidCompressedData))
+                }
+                _ if consumed == data.len() && output.len() < output.capacity() => break,
+                Status::BufError if output.len() < output.capacity() => {
+                    return Err(Error::Protocol(ProtocolError::InvalidCompressedData))
+                }
+                _ => {}
+            }
+        }
+
+        if self.peer_no_context_takeover {
+            self.inflate.reset(false);
+        }
+        Ok(output)
+    }
+}
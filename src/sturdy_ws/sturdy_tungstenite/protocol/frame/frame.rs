@@ -362,7 +362,7 @@ impl Frame {
     #[inline]
     pub fn close(msg: Option<CloseFrame>) -> Frame {
         let payload = if let Some(CloseFrame { code, reason }) = msg {
-            let mut p = Vec::with_capacity(reason.as_bytes().len() + 2);
+            let mut p = Vec::with_capacity(reason.len() + 2);
             p.write_u16::<NetworkEndian>(code.into()).unwrap(); // can't fail
             p.extend_from_slice(reason.as_bytes());
             p
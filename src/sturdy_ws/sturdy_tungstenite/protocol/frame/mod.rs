@@ -10,6 +10,7 @@ use super::{
     error::{CapacityError, Error, Result},
     Message,
 };
+use crate::sturdy_ws::stats::{record_received, record_sent};
 use log::*;
 use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Write};
 
@@ -147,6 +148,11 @@ impl FrameCodec {
         self.max_out_buffer_len = max;
     }
 
+    /// Length of the data waiting in `out_buffer`.
+    pub(super) fn out_buffer_len(&self) -> usize {
+        self.out_buffer.len()
+    }
+
     /// Sets [`Self::buffer_frame`] buffer target length to reach before
     /// writing to the stream.
     pub(super) fn set_out_buffer_write_len(&mut self, len: usize) {
@@ -207,6 +213,7 @@ impl FrameCodec {
         let (header, length) = self.header.take().expect("Bug: no frame header");
         debug_assert_eq!(payload.len() as u64, length);
         let frame = Frame::from_payload(header, payload);
+        record_received(frame.len());
         trace!("received frame {}", frame);
         Ok(Some(frame))
     }
@@ -227,6 +234,7 @@ impl FrameCodec {
         }
 
         trace!("writing frame {}", frame);
+        record_sent(frame.len());
 
         self.out_buffer.reserve(frame.len());
         frame
@@ -248,7 +256,9 @@ impl FrameCodec {
     ///
     /// May write to the stream, will **not** flush.
     ///
-    /// **Safety: The caller must verify the `bytes` are valid frame bytes.**
+    /// # Safety
+    ///
+    /// The caller must verify the `bytes` are valid frame bytes.
     pub(super) unsafe fn buffer_raw_frame_bytes<Stream>(
         &mut self,
         stream: &mut Stream,
@@ -262,11 +272,10 @@ impl FrameCodec {
         }
 
         trace!("writing frame bytes {}", bytes.len());
+        record_sent(bytes.len());
 
         self.out_buffer.reserve(bytes.len());
-        self.out_buffer
-            .write(bytes)
-            .expect("Bug: can't write to vector");
+        self.out_buffer.extend_from_slice(bytes);
 
         if self.out_buffer.len() > self.out_buffer_write_len {
             self.write_out_buffer(stream)
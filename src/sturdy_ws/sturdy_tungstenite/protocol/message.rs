@@ -90,6 +90,8 @@ use self::string_collect::StringCollector;
 #[derive(Debug)]
 pub struct IncompleteMessage {
     collector: IncompleteMessageCollector,
+    /// The type of the message once decompressed, `None` if it isn't compressed.
+    compressed: Option<IncompleteMessageType>,
 }
 
 #[derive(Debug)]
@@ -108,6 +110,29 @@ impl IncompleteMessage {
                     IncompleteMessageCollector::Text(StringCollector::new())
                 }
             },
+            compressed: None,
+        }
+    }
+
+    /// Create new for a compressed message, the payload is collected as is.
+    pub fn new_compressed(message_type: IncompleteMessageType) -> Self {
+        IncompleteMessage {
+            collector: IncompleteMessageCollector::Binary(Vec::new()),
+            compressed: Some(message_type),
+        }
+    }
+
+    /// Takes the collected payload of a compressed message, gives the message back if it isn't
+    /// compressed.
+    pub fn into_compressed(self) -> StdResult<(IncompleteMessageType, Vec<u8>), Self> {
+        match (self.compressed, self.collector) {
+            (Some(message_type), IncompleteMessageCollector::Binary(data)) => {
+                Ok((message_type, data))
+            }
+            (compressed, collector) => Err(IncompleteMessage {
+                collector,
+                compressed,
+            }),
         }
     }
 
@@ -156,6 +181,7 @@ impl IncompleteMessage {
 }
 
 /// The type of incomplete message.
+#[derive(Debug, Clone, Copy)]
 pub enum IncompleteMessageType {
     Text,
     Binary,
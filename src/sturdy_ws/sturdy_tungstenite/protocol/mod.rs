@@ -1,13 +1,15 @@
 //! Generic WebSocket message stream.
 
+pub mod deflate;
 pub mod frame;
 
 mod message;
 
-pub use self::{frame::CloseFrame, message::Message};
+pub use self::{deflate::DeflateConfig, frame::CloseFrame, message::Message};
 pub use super::{error, protocol};
 
 use self::{
+    deflate::{deflate_message, DeflateContext, MIN_COMPRESS_LEN},
     frame::{
         coding::{CloseCode, Control as OpCtl, Data as OpData, OpCode},
         Frame, FrameCodec,
@@ -66,6 +68,9 @@ pub struct WebSocketConfig {
     /// some popular libraries that are sending unmasked frames, ignoring the RFC.
     /// By default this option is set to `false`, i.e. according to RFC 6455.
     pub accept_unmasked_frames: bool,
+    /// The negotiated permessage-deflate parameters, `None` when the extension isn't used.
+    /// The default value is `None`.
+    pub permessage_deflate: Option<DeflateConfig>,
 }
 
 impl Default for WebSocketConfig {
@@ -78,6 +83,7 @@ impl Default for WebSocketConfig {
             max_message_size: Some(64 << 20),
             max_frame_size: Some(16 << 20),
             accept_unmasked_frames: false,
+            permessage_deflate: None,
         }
     }
 }
@@ -159,6 +165,11 @@ impl<Stream> WebSocket<Stream> {
     pub fn can_write(&self) -> bool {
         self.context.can_write()
     }
+
+    /// Number of bytes buffered which couldn't be written to the stream yet.
+    pub fn pending_write_len(&self) -> usize {
+        self.context.pending_write_len()
+    }
 }
 
 impl<Stream: Read + Write> WebSocket<Stream> {
@@ -258,8 +269,10 @@ impl<Stream: Read + Write> WebSocket<Stream> {
     /// - [`Error::Io`] is returned if the underlying connection returns an error
     ///   (consider these fatal except for WouldBlock).
     /// - [`Error::Capacity`] if your message size is bigger than the configured max message size.
-    ///  
-    /// **Safety: The caller must verify the `bytes` are valid frame bytes.**
+    ///
+    /// # Safety
+    ///
+    /// The caller must verify the `bytes` are valid frame bytes.
     pub unsafe fn write_raw<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<()> {
         self.context.write_raw(&mut self.socket, bytes.as_ref())
     }
@@ -331,6 +344,8 @@ pub struct WebSocketContext {
     additional_send: Option<Frame>,
     /// The configuration for the websocket session.
     config: WebSocketConfig,
+    /// permessage-deflate state, if it was negotiated.
+    deflate: Option<DeflateContext>,
 }
 
 impl WebSocketContext {
@@ -357,6 +372,9 @@ impl WebSocketContext {
             state: WebSocketState::Active,
             incomplete: None,
             additional_send: None,
+            deflate: config
+                .permessage_deflate
+                .map(|deflate| DeflateContext::new(deflate, role)),
             config,
         }
     }
@@ -390,6 +408,11 @@ impl WebSocketContext {
         self.state.is_active()
     }
 
+    /// Number of bytes buffered which couldn't be written to the stream yet.
+    pub fn pending_write_len(&self) -> usize {
+        self.frame.out_buffer_len()
+    }
+
     /// Read a message from the provided stream, if possible.
     ///
     /// This function sends pong and close responses automatically.
@@ -443,8 +466,8 @@ impl WebSocketContext {
         }
 
         let frame = match message {
-            Message::Text(data) => Frame::message(data.into(), OpCode::Data(OpData::Text), true),
-            Message::Binary(data) => Frame::message(data, OpCode::Data(OpData::Binary), true),
+            Message::Text(data) => self.message_frame(data.into(), OpData::Text),
+            Message::Binary(data) => self.message_frame(data, OpData::Binary),
             Message::Ping(data) => Frame::ping(data),
             Message::Pong(data) => {
                 self.set_additional(Frame::pong(data));
@@ -474,7 +497,9 @@ impl WebSocketContext {
     /// If the write buffer would exceed the configured [`WebSocketConfig::max_write_buffer_size`]
     /// [`Err(WriteBufferFull2)`](Error::WriteBufferFull2) is returned.
     ///
-    /// **Safety: The caller must verify the `bytes` are valid frame bytes.**
+    /// # Safety
+    ///
+    /// The caller must verify the `bytes` are valid frame bytes.
     pub unsafe fn write_raw<Stream>(&mut self, stream: &mut Stream, bytes: &[u8]) -> Result<()>
     where
         Stream: Read + Write,
@@ -593,6 +618,35 @@ impl WebSocketContext {
         self.flush(stream)
     }
 
+    /// Makes a single frame message, compressed if permessage-deflate is used.
+    fn message_frame(&self, data: Vec<u8>, opdata: OpData) -> Frame {
+        if self.deflate.is_none() || data.len() < MIN_COMPRESS_LEN {
+            return Frame::message(data, OpCode::Data(opdata), true);
+        }
+        let compressed = deflate_message(&data);
+        if compressed.len() >= data.len() {
+            return Frame::message(data, OpCode::Data(opdata), true);
+        }
+        let mut frame = Frame::message(compressed, OpCode::Data(opdata), true);
+        frame.header_mut().rsv1 = true;
+        frame
+    }
+
+    /// Finishes a received message, decompressing it if needed.
+    fn complete_message(&mut self, msg: IncompleteMessage) -> Result<Message> {
+        let (message_type, data) = match msg.into_compressed() {
+            Ok(compressed) => compressed,
+            Err(msg) => return msg.complete(),
+        };
+        let Some(deflate) = self.deflate.as_mut() else {
+            return Err(Error::Protocol(ProtocolError::NonZeroReservedBits));
+        };
+        let data = deflate.inflate_message(data, self.config.max_message_size)?;
+        let mut msg = IncompleteMessage::new(message_type);
+        msg.extend(data, self.config.max_message_size)?;
+        msg.complete()
+    }
+
     /// Try to decode one message frame. May return None.
     fn read_message_frame<Stream>(&mut self, stream: &mut Stream) -> Result<Option<Message>>
     where
@@ -611,12 +665,17 @@ impl WebSocketContext {
             // the negotiated extensions defines the meaning of such a nonzero
             // value, the receiving endpoint MUST _Fail the WebSocket
             // Connection_.
-            {
+            //
+            // permessage-deflate defines rsv1 for the first frame of a data message. (RFC 7692)
+            let compressed = {
                 let hdr = frame.header();
-                if hdr.rsv1 || hdr.rsv2 || hdr.rsv3 {
+                let compressible = self.deflate.is_some()
+                    && matches!(hdr.opcode, OpCode::Data(OpData::Text | OpData::Binary));
+                if (hdr.rsv1 && !compressible) || hdr.rsv2 || hdr.rsv3 {
                     return Err(Error::Protocol(ProtocolError::NonZeroReservedBits));
                 }
-            }
+                hdr.rsv1
+            };
 
             match self.role {
                 Role::Server => {
@@ -679,7 +738,8 @@ impl WebSocketContext {
                                 ));
                             }
                             if fin {
-                                Ok(Some(self.incomplete.take().unwrap().complete()?))
+                                let msg = self.incomplete.take().unwrap();
+                                Ok(Some(self.complete_message(msg)?))
                             } else {
                                 Ok(None)
                             }
@@ -694,12 +754,16 @@ impl WebSocketContext {
                                     OpData::Binary => IncompleteMessageType::Binary,
                                     _ => panic!("Bug: message is not text nor binary"),
                                 };
-                                let mut m = IncompleteMessage::new(message_type);
+                                let mut m = if compressed {
+                                    IncompleteMessage::new_compressed(message_type)
+                                } else {
+                                    IncompleteMessage::new(message_type)
+                                };
                                 m.extend(frame.into_data(), self.config.max_message_size)?;
                                 m
                             };
                             if fin {
-                                Ok(Some(msg.complete()?))
+                                Ok(Some(self.complete_message(msg)?))
                             } else {
                                 self.incomplete = Some(msg);
                                 Ok(None)
@@ -785,7 +849,7 @@ impl WebSocketContext {
         let empty_or_pong = self
             .additional_send
             .as_ref()
-            .map_or(true, |f| f.header().opcode == OpCode::Control(OpCtl::Pong));
+            .is_none_or(|f| f.header().opcode == OpCode::Control(OpCtl::Pong));
         if empty_or_pong {
             self.additional_send.replace(add);
         }
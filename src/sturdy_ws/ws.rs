@@ -26,7 +26,8 @@ use std::{
     task::{Context, Poll},
 };
 
-use super::sturdy_tungstenite::protocol::{self, WebSocketConfig};
+use super::sturdy_tungstenite::protocol::{self, DeflateConfig, WebSocketConfig};
+use super::ws_message::PreparedMessage;
 use super::ws_stream::{self, SplitStream, WebSocketStream};
 
 /// Extractor for establishing WebSocket connections.
@@ -44,6 +45,8 @@ pub struct WebSocketUpgrade<F = DefaultOnFailedUpdgrade> {
     on_upgrade: OnUpgrade,
     on_failed_upgrade: F,
     sec_websocket_protocol: Option<HeaderValue>,
+    /// The permessage-deflate parameters we can accept from the client's offers.
+    deflate_offer: Option<DeflateConfig>,
 }
 
 impl<F> std::fmt::Debug for WebSocketUpgrade<F> {
@@ -53,6 +56,7 @@ impl<F> std::fmt::Debug for WebSocketUpgrade<F> {
             .field("protocol", &self.protocol)
             .field("sec_websocket_key", &self.sec_websocket_key)
             .field("sec_websocket_protocol", &self.sec_websocket_protocol)
+            .field("deflate_offer", &self.deflate_offer)
             .finish_non_exhaustive()
     }
 }
@@ -104,11 +108,11 @@ impl<F> WebSocketUpgrade<F> {
     ///
     /// ```
     /// use axum::{
-    ///     extract::ws::{WebSocketUpgrade, WebSocket},
     ///     routing::get,
-    ///     response::{IntoResponse, Response},
+    ///     response::Response,
     ///     Router,
     /// };
+    /// use sturdy_spoon::sturdy_ws::WebSocketUpgrade;
     ///
     /// let app = Router::new().route("/ws", get(handler));
     ///
@@ -151,6 +155,15 @@ impl<F> WebSocketUpgrade<F> {
         self
     }
 
+    /// Use the permessage-deflate extension (RFC 7692) when the client offers it.
+    ///
+    /// Messages big enough are then compressed, [`PreparedMessage`]s are compressed only once
+    /// for every socket using the extension.
+    pub fn permessage_deflate(mut self) -> Self {
+        self.config.permessage_deflate = self.deflate_offer;
+        self
+    }
+
     /// Provide a callback to call if upgrading the connection fails.
     ///
     /// The connection upgrade is performed in a background task. If that fails this callback
@@ -161,10 +174,8 @@ impl<F> WebSocketUpgrade<F> {
     /// # Example
     ///
     /// ```
-    /// use axum::{
-    ///     extract::{WebSocketUpgrade},
-    ///     response::Response,
-    /// };
+    /// use axum::response::Response;
+    /// use sturdy_spoon::sturdy_ws::WebSocketUpgrade;
     ///
     /// async fn handler(ws: WebSocketUpgrade) -> Response {
     ///     ws.on_failed_upgrade(|error| {
@@ -186,6 +197,7 @@ impl<F> WebSocketUpgrade<F> {
             on_upgrade: self.on_upgrade,
             on_failed_upgrade: callback,
             sec_websocket_protocol: self.sec_websocket_protocol,
+            deflate_offer: self.deflate_offer,
         }
     }
 
@@ -241,6 +253,10 @@ impl<F> WebSocketUpgrade<F> {
             builder = builder.header(header::SEC_WEBSOCKET_PROTOCOL, protocol);
         }
 
+        if let Some(deflate) = config.permessage_deflate {
+            builder = builder.header(header::SEC_WEBSOCKET_EXTENSIONS, deflate.response_header());
+        }
+
         builder.body(body::boxed(body::Empty::new())).unwrap()
     }
 }
@@ -311,12 +327,21 @@ where
 
         let sec_websocket_protocol = parts.headers.get(header::SEC_WEBSOCKET_PROTOCOL).cloned();
 
+        let deflate_offer = DeflateConfig::negotiate(
+            parts
+                .headers
+                .get_all(header::SEC_WEBSOCKET_EXTENSIONS)
+                .iter()
+                .filter_map(|value| value.to_str().ok()),
+        );
+
         Ok(Self {
             config: Default::default(),
             protocol: None,
             sec_websocket_key,
             on_upgrade,
             sec_websocket_protocol,
+            deflate_offer,
             on_failed_upgrade: DefaultOnFailedUpdgrade,
         })
     }
@@ -368,11 +393,29 @@ impl WebSocket {
 
     /// Send raw bytes of a message.
     ///
-    /// **Safety**: The bytes must be valid message bytes.
+    /// The bytes the socket can't take right away are buffered, see
+    /// [`WebSocketUpgrade::max_write_buffer_size`] for the cap, and need to be flushed once
+    /// [`Self::pending_write_len`] isn't zero.
+    ///
+    /// # Safety
+    ///
+    /// The bytes must be valid message bytes.
     pub unsafe fn send_raw<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<(), WsError> {
         self.inner.write_raw(bytes)
     }
 
+    /// Send a message encoded beforehand, without encoding it again.
+    ///
+    /// Like [`Self::send_raw`] the bytes the socket can't take right away are buffered.
+    pub fn send_prepared(&mut self, msg: &PreparedMessage) -> Result<(), WsError> {
+        self.inner.write_prepared(msg)
+    }
+
+    /// Number of bytes buffered which couldn't be written to the socket yet.
+    pub fn pending_write_len(&self) -> usize {
+        self.inner.pending_write_len()
+    }
+
     /// Gracefully close this WebSocket.
     pub async fn close(mut self) -> Result<(), Error> {
         self.inner.close(None).await.map_err(Error::new)
@@ -394,14 +437,10 @@ impl Stream for WebSocket {
     type Item = Result<Message, Error>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        loop {
-            match futures_util::ready!(self.inner.poll_next_unpin(cx)) {
-                Some(Ok(msg)) => {
-                    return Poll::Ready(Some(Ok(msg)));
-                }
-                Some(Err(err)) => return Poll::Ready(Some(Err(Error::new(err)))),
-                None => return Poll::Ready(None),
-            }
+        match futures_util::ready!(self.inner.poll_next_unpin(cx)) {
+            Some(Ok(msg)) => Poll::Ready(Some(Ok(msg))),
+            Some(Err(err)) => Poll::Ready(Some(Err(Error::new(err)))),
+            None => Poll::Ready(None),
         }
     }
 }
@@ -442,7 +481,7 @@ impl Sink<Message> for WebSocket {
     }
 }
 
-fn sign(key: &[u8]) -> HeaderValue {
+pub(super) fn sign(key: &[u8]) -> HeaderValue {
     use base64::engine::Engine as _;
 
     let mut sha1 = Sha1::default();
@@ -460,17 +499,13 @@ pub mod rejection {
             body_text = $body_text:expr,
             status = $status:expr,
         ) => {
-            #[cfg(feature = "tracing")]
-            {
-                tracing::event!(
-                    target: "axum::rejection",
-                    tracing::Level::TRACE,
-                    status = $status.as_u16(),
-                    body = $body_text,
-                    rejection_type = std::any::type_name::<$ty>(),
-                    "rejecting request",
-                );
-            }
+            log::trace!(
+                target: "axum::rejection",
+                "rejecting request: status={} body={} rejection_type={}",
+                $status.as_u16(),
+                $body_text,
+                std::any::type_name::<$ty>(),
+            );
         };
     }
 
@@ -1,9 +1,15 @@
-use std::sync::Arc;
+use std::{
+    io::Cursor,
+    sync::{Arc, OnceLock},
+};
 
 use super::sturdy_tungstenite::{
-    protocol::frame::{
-        coding::{Data, OpCode},
-        CloseFrame, Frame,
+    protocol::{
+        deflate::{deflate_message, MIN_COMPRESS_LEN},
+        frame::{
+            coding::{Data, OpCode},
+            CloseFrame, Frame, FrameHeader,
+        },
     },
     Message,
 };
@@ -15,6 +21,12 @@ pub enum WebSocketMessage {
 }
 
 impl WebSocketMessage {
+    /// Encodes the message once, see [`PreparedMessage`].
+    #[inline]
+    pub fn prepare(self) -> PreparedMessage {
+        self.into()
+    }
+
     pub fn into_server_shared_bytes(self) -> Arc<[u8]> {
         let frame: Frame = self.into();
         let bytes: Vec<u8> = frame.into();
@@ -31,6 +43,90 @@ impl WebSocketMessage {
     }
 }
 
+/// A message encoded once as a final, unmasked server frame, which can be cheaply cloned and
+/// sent to many sockets without encoding it again.
+///
+/// It can only be built from a [`WebSocketMessage`], so the bytes are always a valid frame.
+#[derive(Debug, Clone)]
+pub struct PreparedMessage(Arc<PreparedFrames>);
+
+#[derive(Debug)]
+struct PreparedFrames {
+    plain: Box<[u8]>,
+    /// The permessage-deflate variant, only made once a socket using the extension needs it.
+    deflated: OnceLock<Option<Box<[u8]>>>,
+}
+
+impl PreparedMessage {
+    /// The encoded frame, header included.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0.plain
+    }
+
+    /// The encoded frame for sockets using permessage-deflate, compressed when it's worth it.
+    pub fn as_deflated_bytes(&self) -> &[u8] {
+        self.0
+            .deflated
+            .get_or_init(|| deflate_frame(&self.0.plain))
+            .as_deref()
+            .unwrap_or(&self.0.plain)
+    }
+
+    /// The text of the message, `None` if it isn't a text message.
+    #[inline]
+    pub fn as_text(&self) -> Option<&str> {
+        text_from_server_shared_bytes(&self.0.plain)
+    }
+}
+
+impl From<WebSocketMessage> for PreparedMessage {
+    fn from(value: WebSocketMessage) -> Self {
+        let frame: Frame = value.into();
+        let plain: Vec<u8> = frame.into();
+        Self(Arc::new(PreparedFrames {
+            plain: plain.into_boxed_slice(),
+            deflated: OnceLock::new(),
+        }))
+    }
+}
+
+/// Compresses a frame made by [`PreparedMessage`], `None` if it isn't a data frame or
+/// compressing doesn't make it smaller.
+fn deflate_frame(plain: &[u8]) -> Option<Box<[u8]>> {
+    let mut cursor = Cursor::new(plain);
+    let (header, length) = FrameHeader::parse(&mut cursor).ok()??;
+    let opcode @ OpCode::Data(Data::Text | Data::Binary) = header.opcode else {
+        return None;
+    };
+    if (length as usize) < MIN_COMPRESS_LEN {
+        return None;
+    }
+    let payload = &plain[cursor.position() as usize..];
+    let compressed = deflate_message(payload);
+    if compressed.len() >= payload.len() {
+        return None;
+    }
+    let mut frame = Frame::message(compressed, opcode, true);
+    frame.header_mut().rsv1 = true;
+    let bytes: Vec<u8> = frame.into();
+    Some(bytes.into_boxed_slice())
+}
+
+/// Reads back the text of bytes made by [`WebSocketMessage::into_server_shared_bytes`].
+///
+/// Returns `None` if the bytes aren't a single final and unmasked text frame.
+pub fn text_from_server_shared_bytes(bytes: &[u8]) -> Option<&str> {
+    let mut cursor = Cursor::new(bytes);
+    let (header, length) = FrameHeader::parse(&mut cursor).ok()??;
+    if !header.is_final || header.mask.is_some() || header.opcode != OpCode::Data(Data::Text) {
+        return None;
+    }
+    let start = cursor.position() as usize;
+    let end = start.checked_add(usize::try_from(length).ok()?)?;
+    std::str::from_utf8(bytes.get(start..end)?).ok()
+}
+
 impl From<WebSocketMessage> for Frame {
     fn from(value: WebSocketMessage) -> Self {
         match value {
@@ -29,6 +29,7 @@ use std::{
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use super::sturdy_tungstenite::protocol::CloseFrame;
+use super::ws_message::PreparedMessage;
 
 /// A wrapper around an underlying raw stream which implements the WebSocket
 /// protocol.
@@ -129,12 +130,42 @@ impl<S> WebSocketStream<S> {
 
     /// Write raw frame bytes to the underlying web socket
     ///
-    /// **Safety**: The bytes must be valid frame bytes.
+    /// Whatever the socket can't take right away stays buffered, up to the configured
+    /// `max_write_buffer_size`, and is written by the next write or flush. Use
+    /// [`Self::pending_write_len`] to know whether a flush is still needed.
+    ///
+    /// # Safety
+    ///
+    /// The bytes must be valid frame bytes.
     pub unsafe fn write_raw<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<(), WsError>
     where
         S: AsyncRead + AsyncWrite + Unpin,
     {
-        self.inner.write_raw(bytes)
+        match self.inner.write_raw(bytes) {
+            // The bytes were accepted and buffered, it isn't an error.
+            Err(WsError::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+            other => other,
+        }
+    }
+
+    /// Write a message encoded beforehand to the underlying web socket, see
+    /// [`Self::write_raw`] for the buffering.
+    pub fn write_prepared(&mut self, msg: &PreparedMessage) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let bytes = if self.get_config().permessage_deflate.is_some() {
+            msg.as_deflated_bytes()
+        } else {
+            msg.as_bytes()
+        };
+        // SAFETY: `PreparedMessage` can only hold valid final server frames.
+        unsafe { self.write_raw(bytes) }
+    }
+
+    /// Number of bytes buffered which couldn't be written to the underlying stream yet.
+    pub fn pending_write_len(&self) -> usize {
+        self.inner.pending_write_len()
     }
 }
 
@@ -0,0 +1,200 @@
+use axum::extract::State;
+use axum::middleware;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+use serde::Deserialize;
+use serde::Serialize;
+use tower_cookies::cookie::time::Duration;
+use tower_cookies::cookie::time::OffsetDateTime;
+use tower_cookies::cookie::SameSite;
+use tower_cookies::Cookie;
+use tower_cookies::Cookies;
+
+use crate::accounts::{
+    Account, AccountError, Accounts, Session, Visit, SESSION_COOKIE, SESSION_EXPIRATION,
+};
+use crate::common::validation::{self, ValidationErrors};
+use crate::rate_limit;
+use crate::server_state::ServerState;
+
+#[derive(Debug, Deserialize)]
+struct RegisterPayload {
+    username: String,
+    password: String,
+    /// The name shown in rooms, the username when missing.
+    #[serde(default)]
+    display_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginPayload {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DisplayNamePayload {
+    display_name: String,
+}
+
+#[derive(Serialize)]
+struct AccountInfo {
+    username: String,
+    display_name: String,
+}
+
+impl From<Account> for AccountInfo {
+    fn from(account: Account) -> Self {
+        Self {
+            username: account.username,
+            display_name: account.display_name,
+        }
+    }
+}
+
+pub(super) fn routes(server_state: ServerState) -> Router {
+    let limit_login =
+        middleware::from_fn_with_state(&server_state.rate_limits.login, rate_limit::limit);
+    Router::new()
+        .route("/register", post(register).route_layer(limit_login.clone()))
+        .route("/login", post(login).route_layer(limit_login))
+        .route("/logout", post(logout))
+        .route("/me", get(me).post(set_display_name))
+        .route("/history", get(history))
+        .with_state(server_state)
+}
+
+fn accounts(state: &ServerState) -> Result<&'static Accounts, AccountError> {
+    state.accounts.ok_or(AccountError::Disabled)
+}
+
+/// Runs the password hashing off the async threads, it takes a while on purpose.
+async fn blocking<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> R {
+    tokio::task::spawn_blocking(f)
+        .await
+        .expect("account task panicked")
+}
+
+/// The account the browser is logged in with, banned accounts can't do anything with it.
+async fn logged_in(cookies: &Cookies, state: &ServerState) -> Result<Account, AccountError> {
+    let account = state
+        .session_account(cookies)
+        .await
+        .ok_or(AccountError::NotLoggedIn)?;
+    if account.banned {
+        return Err(AccountError::Banned);
+    }
+    Ok(account)
+}
+
+fn set_session_cookie(cookies: &Cookies, account: &Account, state: &ServerState) {
+    let token = Session::new(account).encode(&state.ws_state.keys);
+    let mut session_cookie = Cookie::new(SESSION_COOKIE, token);
+    session_cookie.set_path("/");
+    session_cookie
+        .set_expires(OffsetDateTime::now_utc() + Duration::milliseconds(SESSION_EXPIRATION as i64));
+    session_cookie.set_http_only(true);
+    session_cookie.set_same_site(SameSite::Lax);
+    cookies.add(session_cookie);
+}
+
+async fn register(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    Json(payload): Json<RegisterPayload>,
+) -> Result<Json<AccountInfo>, axum::response::Response> {
+    let accounts = accounts(&state).map_err(IntoResponse::into_response)?;
+    let display_name = payload
+        .display_name
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| payload.username.clone());
+
+    let mut errors = ValidationErrors::default();
+    errors.check("username", validation::check_username(&payload.username));
+    errors.check("password", validation::check_password(&payload.password));
+    errors.check("display_name", validation::check_user_name(&display_name));
+    errors.into_result().map_err(IntoResponse::into_response)?;
+
+    let account =
+        blocking(move || accounts.register(&payload.username, &payload.password, &display_name))
+            .await
+            .map_err(IntoResponse::into_response)?;
+    log::info!("account {} registered", account.username);
+    set_session_cookie(&cookies, &account, &state);
+    Ok(Json(account.into()))
+}
+
+async fn login(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    Json(payload): Json<LoginPayload>,
+) -> Result<Json<AccountInfo>, AccountError> {
+    let accounts = accounts(&state)?;
+    let account = blocking(move || accounts.login(&payload.username, &payload.password)).await?;
+    set_session_cookie(&cookies, &account, &state);
+    Ok(Json(account.into()))
+}
+
+/// Ends the session on every browser of the account, a copied session cookie stops working too.
+async fn logout(cookies: Cookies, State(state): State<ServerState>) -> impl IntoResponse {
+    let account = state.session_account(&cookies).await;
+    if let (Some(accounts), Some(account)) = (state.accounts, account) {
+        let key = account.key();
+        if let Err(err) = blocking(move || accounts.end_sessions(&key)).await {
+            log::warn!(
+                "failed to end the sessions of {}: {}",
+                account.username,
+                err
+            );
+        }
+    }
+    let mut session_cookie = Cookie::named(SESSION_COOKIE);
+    session_cookie.set_path("/");
+    cookies.remove(session_cookie);
+    http::StatusCode::NO_CONTENT
+}
+
+async fn me(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+) -> Result<Json<AccountInfo>, AccountError> {
+    accounts(&state)?;
+    Ok(Json(logged_in(&cookies, &state).await?.into()))
+}
+
+/// Changes the name the account is shown with, rooms joined from now on use it.
+async fn set_display_name(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    Json(payload): Json<DisplayNamePayload>,
+) -> Result<Json<AccountInfo>, axum::response::Response> {
+    let accounts = accounts(&state).map_err(IntoResponse::into_response)?;
+    let account = logged_in(&cookies, &state)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let mut errors = ValidationErrors::default();
+    errors.check(
+        "display_name",
+        validation::check_user_name(&payload.display_name),
+    );
+    errors.into_result().map_err(IntoResponse::into_response)?;
+
+    let account = accounts
+        .set_display_name(&account.username, &payload.display_name)
+        .map_err(IntoResponse::into_response)?;
+    Ok(Json(account.into()))
+}
+
+/// The rooms the account created or joined, from whichever browser.
+async fn history(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+) -> Result<Json<Vec<Visit>>, AccountError> {
+    let accounts = accounts(&state)?;
+    let account = logged_in(&cookies, &state).await?;
+    Ok(Json(accounts.history(&account.key())?))
+}
@@ -0,0 +1,298 @@
+use std::str::FromStr;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::headers;
+use axum::headers::authorization::Bearer;
+use axum::routing::get;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+use axum::TypedHeader;
+use http::StatusCode;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+
+use internal_server_error::InternalServerError;
+
+use crate::accounts::AccountError;
+use crate::common::nanoid::{IdFormat, NanoId};
+use crate::common::utils::Redacted;
+use crate::server_state::ServerState;
+use crate::ws_handler::room_state::RoomState;
+use crate::ws_handler::ws_state::{Disconnected, UserInfo};
+use crate::ws_handler::PlayerType;
+use crate::ws_handler::StateType;
+
+#[derive(Debug, Error, InternalServerError)]
+enum AdminError {
+    #[error("Not Found")]
+    #[code(StatusCode::NOT_FOUND)]
+    Disabled,
+    #[error("A valid admin token is required.")]
+    #[code(StatusCode::UNAUTHORIZED)]
+    Unauthorized,
+    #[error("Bad Id was provided.")]
+    #[code(StatusCode::BAD_REQUEST)]
+    BadId,
+    #[error("The spcified room doesn't exist.")]
+    #[code(StatusCode::NOT_FOUND)]
+    NoRoom,
+    #[error("The spcified user doesn't exist.")]
+    #[code(StatusCode::NOT_FOUND)]
+    NoUser,
+    #[error("The spcified account doesn't exist.")]
+    #[code(StatusCode::NOT_FOUND)]
+    NoAccount,
+    #[error("The account store failed.")]
+    #[code(StatusCode::INTERNAL_SERVER_ERROR)]
+    Storage,
+}
+
+impl From<AccountError> for AdminError {
+    fn from(err: AccountError) -> Self {
+        match err {
+            AccountError::Disabled => AdminError::Disabled,
+            AccountError::NoAccount => AdminError::NoAccount,
+            _ => AdminError::Storage,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AnnouncePayload {
+    message: String,
+}
+
+#[derive(Serialize)]
+struct RoomSummary {
+    id: String,
+    name: String,
+    users: u32,
+    max_users: u32,
+    spectators: u32,
+    max_spectators: u32,
+    state: StateType,
+    time: usize, // in Miliseconds
+}
+
+#[derive(Serialize)]
+struct RoomDetails {
+    #[serde(flatten)]
+    summary: RoomSummary,
+    url: String,
+    cc_url: String,
+    current_player: PlayerType,
+    members: Vec<UserInfo>,
+}
+
+#[derive(Serialize)]
+struct AnnounceResult {
+    rooms: usize,
+}
+
+#[derive(Serialize)]
+struct BanResult {
+    username: String,
+    banned: bool,
+    /// Connections of the account which were closed.
+    kicked: usize,
+    /// Connections of the account which were dropped, they didn't take the close frame.
+    dropped: usize,
+}
+
+pub(super) fn routes(server_state: ServerState) -> Router {
+    Router::new()
+        .route("/rooms", get(list_rooms))
+        .route("/rooms/:id", get(inspect_room).delete(close_room))
+        .route("/users/:id/kick", post(kick_user))
+        .route("/announce", post(announce))
+        .route(
+            "/accounts/:username/ban",
+            post(ban_account).delete(unban_account),
+        )
+        .with_state(server_state)
+}
+
+/// Compares without bailing out on the first mismatch, so the token can't be guessed by timing.
+pub(super) fn token_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn authorize(
+    state: &ServerState,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+) -> Result<(), AdminError> {
+    // Behave as if the admin API doesn't exist when no token is configured.
+    let Some(admin_token) = state.admin_token else {
+        return Err(AdminError::Disabled);
+    };
+    let Some(TypedHeader(headers::Authorization(bearer))) = bearer else {
+        return Err(AdminError::Unauthorized);
+    };
+    if !token_eq(bearer.token().as_bytes(), admin_token.as_bytes()) {
+        return Err(AdminError::Unauthorized);
+    }
+    Ok(())
+}
+
+fn parse_id<F: IdFormat>(id: &str) -> Result<NanoId<F>, AdminError> {
+    NanoId::from_str(id).map_err(|_| AdminError::BadId)
+}
+
+async fn room_summary(room: &RoomState) -> RoomSummary {
+    let (state, time) = room
+        .read_data(|data| (data.get_state(), data.get_time()))
+        .await;
+    RoomSummary {
+        id: room.get_id().to_string(),
+        name: room.get_name().to_owned(),
+        users: room.get_user_count(),
+        max_users: room.get_max_users(),
+        spectators: room.get_spectator_count(),
+        max_spectators: room.get_max_spectators(),
+        state,
+        time,
+    }
+}
+
+async fn list_rooms(
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+) -> Result<Json<Vec<RoomSummary>>, AdminError> {
+    authorize(&state, bearer)?;
+    let rooms = state.ws_state.get_rooms();
+    let mut summaries = Vec::with_capacity(rooms.len());
+    for room in rooms {
+        summaries.push(room_summary(&room).await);
+    }
+    Ok(Json(summaries))
+}
+
+async fn inspect_room(
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    Path(id): Path<String>,
+) -> Result<Json<RoomDetails>, AdminError> {
+    authorize(&state, bearer)?;
+    let room_id = parse_id(&id)?;
+    let room = state
+        .ws_state
+        .get_room(room_id)
+        .map_err(|_| AdminError::NoRoom)?;
+    let (url, cc_url, current_player) = room
+        .read_data(|data| (data.get_url(), data.get_cc_url(), data.get_current_player()))
+        .await;
+    Ok(Json(RoomDetails {
+        summary: room_summary(&room).await,
+        url,
+        cc_url,
+        current_player,
+        members: state.ws_state.get_room_users(room_id),
+    }))
+}
+
+async fn close_room(
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AdminError> {
+    authorize(&state, bearer)?;
+    let room_id = parse_id(&id)?;
+    let disconnected = state
+        .ws_state
+        .close_room(room_id)
+        .await
+        .map_err(|_| AdminError::NoRoom)?;
+    log::warn!(
+        "room={} closed by an admin, {} users notified and {} dropped",
+        room_id,
+        disconnected.closed,
+        disconnected.dropped
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn kick_user(
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AdminError> {
+    authorize(&state, bearer)?;
+    let user_id = parse_id(&id)?;
+    let disconnect = state
+        .ws_state
+        .kick_user(user_id)
+        .await
+        .map_err(|_| AdminError::NoUser)?;
+    log::warn!("user={} kicked by an admin ({:?})", user_id, disconnect);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn announce(
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    Json(payload): Json<AnnouncePayload>,
+) -> Result<Json<AnnounceResult>, AdminError> {
+    authorize(&state, bearer)?;
+    let rooms = state.ws_state.announce(&payload.message);
+    log::warn!(
+        "admin announced to {} rooms: {}",
+        rooms,
+        Redacted(&payload.message)
+    );
+    Ok(Json(AnnounceResult { rooms }))
+}
+
+async fn set_banned(
+    state: &ServerState,
+    username: &str,
+    banned: bool,
+) -> Result<BanResult, AdminError> {
+    let accounts = state.accounts.ok_or(AccountError::Disabled)?;
+    let account = accounts.set_banned(username, banned)?;
+    let key = account.key();
+    // Banned accounts are thrown out of their rooms right away, not on their next visit.
+    let disconnected = if banned {
+        state.ws_state.kick_account(&key).await
+    } else {
+        Disconnected::default()
+    };
+    Ok(BanResult {
+        username: key,
+        banned,
+        kicked: disconnected.closed,
+        dropped: disconnected.dropped,
+    })
+}
+
+async fn ban_account(
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    Path(username): Path<String>,
+) -> Result<Json<BanResult>, AdminError> {
+    authorize(&state, bearer)?;
+    let result = set_banned(&state, &username, true).await?;
+    log::warn!(
+        "account {} banned by an admin, {} connections closed and {} dropped",
+        result.username,
+        result.kicked,
+        result.dropped
+    );
+    Ok(Json(result))
+}
+
+async fn unban_account(
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    Path(username): Path<String>,
+) -> Result<Json<BanResult>, AdminError> {
+    authorize(&state, bearer)?;
+    let result = set_banned(&state, &username, false).await?;
+    log::warn!("account {} unbanned by an admin", result.username);
+    Ok(Json(result))
+}
@@ -0,0 +1,353 @@
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::headers;
+use axum::headers::authorization::Bearer;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::routing::get;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+use axum::TypedHeader;
+use http::StatusCode;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+use tower_cookies::Cookie;
+use tower_cookies::Cookies;
+
+use internal_server_error::InternalServerError;
+
+use crate::basic_auth::OwnerAuth;
+use crate::basic_auth::API_TOKEN_EXPIRATION;
+use crate::basic_auth::DEVICE_COOKIE;
+use crate::basic_auth::OWNER_AUTH_COOKIE;
+use crate::client_addr::ClientAddr;
+use crate::common::utils::get_elapsed_milis;
+use crate::common::validation::{self, ValidationErrors};
+use crate::common::RoomId;
+use crate::server_state::ServerState;
+use crate::ws_handler::room_state::RoomState;
+use crate::ws_handler::video_time_from_secs;
+use crate::ws_handler::PlayerType;
+use crate::ws_handler::StateType;
+use crate::ws_handler::PLAYER_MAX;
+
+#[derive(Debug, Error, InternalServerError)]
+enum RoomControlError {
+    #[error("Unknown User agent")]
+    #[code(StatusCode::FORBIDDEN)]
+    UnknownUserAgent,
+    #[error("Bad Room Id was provided.")]
+    #[code(StatusCode::BAD_REQUEST)]
+    BadRoomId,
+    #[error("The spcified room doesn't exist.")]
+    #[code(StatusCode::BAD_REQUEST)]
+    NoRoom,
+    #[error("Only the owner of the room can control it.")]
+    #[code(StatusCode::UNAUTHORIZED)]
+    Unauthorized,
+    #[error("Time out of bounds.")]
+    #[code(StatusCode::BAD_REQUEST)]
+    TimeOutOfBounds,
+}
+
+#[derive(Debug, Deserialize)]
+struct TimePayload {
+    /// In seconds, same as the `play`, `pause` and `seek` packets, the current video time is
+    /// used when left out.
+    time: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SeekPayload {
+    /// In seconds.
+    time: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct VideoPayload {
+    video_url: String,
+    cc_url: String,
+    player_index: PlayerType,
+}
+
+#[derive(Serialize)]
+struct ApiToken {
+    token: String,
+    /// In seconds.
+    expires_in: u64,
+}
+
+#[derive(Serialize)]
+struct RoomStatus {
+    id: String,
+    name: String,
+    users: u32,
+    max_users: u32,
+    spectators: u32,
+    max_spectators: u32,
+    url: String,
+    cc_url: String,
+    time: usize, // in Miliseconds
+    state: StateType,
+    current_player: PlayerType,
+}
+
+pub(super) fn routes(server_state: ServerState) -> Router {
+    Router::new()
+        .route("/:id/play", post(play))
+        .route("/:id/pause", post(pause))
+        .route("/:id/seek", post(seek))
+        .route("/:id/video", post(video))
+        .route("/:id/state", get(state))
+        .route("/:id/api-token", post(api_token))
+        .with_state(server_state)
+}
+
+/// Resolves the room and makes sure the request comes from its owner, see [`authorize_owner`].
+///
+/// Bots have no browser to keep a device secret in, they send an API token from `/api-token` as
+/// the bearer token instead. That one works on its own, the owner token never does.
+async fn authorize(
+    cookies: &Cookies,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    addr: &SocketAddr,
+    id: &str,
+    state: &ServerState,
+) -> Result<Arc<RoomState>, RoomControlError> {
+    let (room_id, bearer) = check_request(bearer, user_agent, id)?;
+    let api_auth = bearer
+        .as_ref()
+        .and_then(|token| OwnerAuth::from_api_token(token, &state.ws_state.keys).ok());
+    if let Some(auth) = api_auth {
+        if !auth.is_valid_api(&room_id) {
+            return Err(RoomControlError::Unauthorized);
+        }
+        return state
+            .ws_state
+            .get_room(room_id)
+            .map_err(|_| RoomControlError::NoRoom);
+    }
+    authorize_owner(cookies, bearer, addr, room_id, state).await
+}
+
+/// Checks what every endpoint needs, the bearer token is handed back as is.
+fn check_request(
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    id: &str,
+) -> Result<(RoomId, Option<String>), RoomControlError> {
+    if user_agent.is_none() {
+        return Err(RoomControlError::UnknownUserAgent);
+    }
+    let room_id = RoomId::from_str(id).map_err(|_| RoomControlError::BadRoomId)?;
+    let bearer =
+        bearer.map(|TypedHeader(headers::Authorization(bearer))| bearer.token().to_owned());
+    Ok((room_id, bearer))
+}
+
+/// Makes sure the request comes from the owner itself, the owner token is taken from the
+/// `Authorization: Bearer` header or the `owner_auth` cookie set by `/room/create`. It's only
+/// accepted next to the `device_secret` cookie of the browser which created the room. A browser
+/// logged in with the account which created the room needs no token.
+async fn authorize_owner(
+    cookies: &Cookies,
+    bearer: Option<String>,
+    addr: &SocketAddr,
+    room_id: RoomId,
+    state: &ServerState,
+) -> Result<Arc<RoomState>, RoomControlError> {
+    let token = bearer.or_else(|| {
+        cookies
+            .get(OWNER_AUTH_COOKIE)
+            .map(|cookie| cookie.value().to_owned())
+    });
+    let account = state.session_account_key(cookies).await;
+    let is_owner = match token.map(|token| OwnerAuth::from_token(token, &state.ws_state.keys)) {
+        None => false,
+        Some(Err(e)) => {
+            log::debug!("room={} peer={} OwnerAuth error: {}", room_id, addr, e);
+            false
+        }
+        Some(Ok(auth)) => auth.is_valid_room_id(
+            cookies.get(DEVICE_COOKIE).as_ref().map(Cookie::value),
+            account.as_deref(),
+            &room_id,
+        ),
+    };
+    let room = state.ws_state.get_room(room_id).ok();
+    let is_owner = is_owner
+        || account.is_some_and(|account| {
+            room.as_ref()
+                .is_some_and(|room| room.get_owner_account() == Some(account.as_str()))
+        });
+    if !is_owner {
+        return Err(RoomControlError::Unauthorized);
+    }
+
+    room.ok_or(RoomControlError::NoRoom)
+}
+
+async fn room_status(room: &RoomState) -> RoomStatus {
+    room.read_data(|data| RoomStatus {
+        id: room.get_id().to_string(),
+        name: room.get_name().to_owned(),
+        users: room.get_user_count(),
+        max_users: room.get_max_users(),
+        spectators: room.get_spectator_count(),
+        max_spectators: room.get_max_spectators(),
+        url: data.get_url(),
+        cc_url: data.get_cc_url(),
+        time: data.get_time(),
+        state: data.get_state(),
+        current_player: data.get_current_player(),
+    })
+    .await
+}
+
+/// Converts the optional payload time, falling back to the room's current video time.
+async fn resolve_time(room: &RoomState, time: Option<f32>) -> Result<usize, RoomControlError> {
+    match time {
+        Some(time) => video_time_from_secs(time).ok_or(RoomControlError::TimeOutOfBounds),
+        None => Ok(room.read_data(|data| data.get_time()).await),
+    }
+}
+
+async fn play(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ClientAddr(addr): ClientAddr,
+    Path(id): Path<String>,
+    Json(payload): Json<TimePayload>,
+) -> Result<Json<RoomStatus>, RoomControlError> {
+    let room = authorize(&cookies, bearer, user_agent, &addr, &id, &state).await?;
+    let time = resolve_time(&room, payload.time).await?;
+    // Nobody being connected to the room isn't an error for us.
+    let _ = room.play(time).await;
+    log::info!(
+        "room={} peer={} owner control: play at {}ms",
+        room.get_id(),
+        addr,
+        time
+    );
+    Ok(Json(room_status(&room).await))
+}
+
+async fn pause(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ClientAddr(addr): ClientAddr,
+    Path(id): Path<String>,
+    Json(payload): Json<TimePayload>,
+) -> Result<Json<RoomStatus>, RoomControlError> {
+    let room = authorize(&cookies, bearer, user_agent, &addr, &id, &state).await?;
+    let time = resolve_time(&room, payload.time).await?;
+    let _ = room.pause(time).await;
+    log::info!(
+        "room={} peer={} owner control: pause at {}ms",
+        room.get_id(),
+        addr,
+        time
+    );
+    Ok(Json(room_status(&room).await))
+}
+
+async fn seek(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ClientAddr(addr): ClientAddr,
+    Path(id): Path<String>,
+    Json(payload): Json<SeekPayload>,
+) -> Result<Json<RoomStatus>, RoomControlError> {
+    let room = authorize(&cookies, bearer, user_agent, &addr, &id, &state).await?;
+    let time = resolve_time(&room, Some(payload.time)).await?;
+    let _ = room.seek(time).await;
+    log::info!(
+        "room={} peer={} owner control: seek at {}ms",
+        room.get_id(),
+        addr,
+        time
+    );
+    Ok(Json(room_status(&room).await))
+}
+
+async fn video(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ClientAddr(addr): ClientAddr,
+    Path(id): Path<String>,
+    Json(payload): Json<VideoPayload>,
+) -> Result<Json<RoomStatus>, Response> {
+    let room = authorize(&cookies, bearer, user_agent, &addr, &id, &state)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let mut errors = ValidationErrors::default();
+    errors.check("video_url", validation::check_url(&payload.video_url));
+    if !payload.cc_url.is_empty() {
+        errors.check("cc_url", validation::check_url(&payload.cc_url));
+    }
+    errors.check(
+        "player_index",
+        validation::check_range(payload.player_index.into(), 0, PLAYER_MAX.into()),
+    );
+    errors.into_result().map_err(IntoResponse::into_response)?;
+    let _ = room
+        .change_video(payload.video_url, payload.cc_url, payload.player_index)
+        .await;
+    log::info!(
+        "room={} peer={} owner control: video changed",
+        room.get_id(),
+        addr
+    );
+    Ok(Json(room_status(&room).await))
+}
+
+async fn state(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ClientAddr(addr): ClientAddr,
+    Path(id): Path<String>,
+) -> Result<Json<RoomStatus>, RoomControlError> {
+    let room = authorize(&cookies, bearer, user_agent, &addr, &id, &state).await?;
+    Ok(Json(room_status(&room).await))
+}
+
+/// Hands the owner a token for bots, see [`authorize`].
+async fn api_token(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ClientAddr(addr): ClientAddr,
+    Path(id): Path<String>,
+) -> Result<Json<ApiToken>, RoomControlError> {
+    // Only the owner can get one, an API token can't renew itself past its expiration.
+    let (room_id, bearer) = check_request(bearer, user_agent, &id)?;
+    let room = authorize_owner(&cookies, bearer, &addr, room_id, &state).await?;
+    let auth = OwnerAuth::new_api(room.get_id(), get_elapsed_milis() + API_TOKEN_EXPIRATION);
+    log::info!(
+        "room={} peer={} owner control: API token issued",
+        room.get_id(),
+        addr
+    );
+    Ok(Json(ApiToken {
+        token: auth.encode(&state.ws_state.keys),
+        expires_in: (API_TOKEN_EXPIRATION / 1000) as u64,
+    }))
+}
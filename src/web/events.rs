@@ -0,0 +1,104 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::sse::Event;
+use axum::response::sse::KeepAlive;
+use axum::response::sse::Sse;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use futures_util::stream;
+use futures_util::Stream;
+use futures_util::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::metrics;
+use crate::server_state::ServerState;
+use crate::ws_handler::check_str_packet;
+
+/// The packets a read-only view follows, the rest of the room traffic isn't for outsiders.
+const EVENT_PACKETS: [&str; 7] = [
+    "state",
+    "play",
+    "pause",
+    "seek",
+    "video_data",
+    "joined",
+    "left",
+];
+
+pub(super) fn routes(server_state: ServerState) -> Router {
+    Router::new()
+        .route("/:id/events", get(events))
+        .with_state(server_state)
+}
+
+/// Turns a string packet into an event named after the packet type, the data being a JSON array
+/// of the packet arguments.
+fn packet_event(packet_type: &str, args: &str) -> Event {
+    let args: Vec<&str> = args.split("|.|").collect();
+    Event::default()
+        .event(packet_type)
+        .json_data(args)
+        .expect("Shouldn't fail")
+}
+
+/// Read-only view of a room, it doesn't join the room so it never takes away a user slot.
+///
+/// The room can be given by its id, its short code or its slug, like when joining it.
+async fn events(
+    State(state): State<ServerState>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Response> {
+    let room = state
+        .ws_state
+        .resolve_room(&id)
+        .and_then(|id| state.ws_state.get_room(id))
+        .map_err(|e| e.into_response())?;
+
+    // Subscribe first so nothing is missed between the snapshot and the first update.
+    let broadcast_rx = room.subscribe();
+    let video_data = room.get_video_data_json().await;
+    // Only the receiver keeps the room around, so the stream ends once the room is gone.
+    let room = Arc::downgrade(&room);
+
+    let snapshot = stream::once(async move { Ok(packet_event("video_data", &video_data)) });
+    let updates = stream::unfold(broadcast_rx, move |mut broadcast_rx| {
+        let room = room.clone();
+        async move {
+            loop {
+                match broadcast_rx.recv().await {
+                    Ok(msg) => {
+                        let Some((packet_type, args)) = msg
+                            .as_text()
+                            .and_then(check_str_packet)
+                            .filter(|(packet_type, _)| EVENT_PACKETS.contains(packet_type))
+                        else {
+                            continue;
+                        };
+                        return Some((Ok(packet_event(packet_type, args)), broadcast_rx));
+                    }
+                    // Like for sockets, skip what is still queued and catch up with a fresh
+                    // snapshot so the position isn't left stale.
+                    Err(RecvError::Lagged(skipped)) => {
+                        metrics::broadcast_lagged(skipped);
+                        let room = room.upgrade()?;
+                        let broadcast_rx = broadcast_rx.resubscribe();
+                        let video_data = room.get_video_data_json().await;
+                        return Some((Ok(packet_event("video_data", &video_data)), broadcast_rx));
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+
+    // Hyper waits for the open responses before exiting, so end with the shutdown as well.
+    let stream = snapshot
+        .chain(updates)
+        .take_until(state.ws_state.shutdown_started());
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,43 @@
+use axum::extract::State;
+use axum::headers;
+use axum::headers::authorization::Bearer;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Router, TypedHeader};
+use http::{header, StatusCode};
+
+use super::admin::token_eq;
+use crate::metrics;
+use crate::server_state::ServerState;
+
+pub(super) fn routes(server_state: ServerState) -> Router {
+    Router::new()
+        .route("/metrics", get(render_metrics))
+        .with_state(server_state)
+}
+
+/// Guarded by a bearer token, the metrics tell how busy the server is.
+async fn render_metrics(
+    State(state): State<ServerState>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+) -> Response {
+    // Behave as if the metrics don't exist when no token is configured.
+    let Some(metrics_token) = state.metrics_token else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let authorized = bearer.is_some_and(|TypedHeader(headers::Authorization(bearer))| {
+        token_eq(bearer.token().as_bytes(), metrics_token.as_bytes())
+    });
+    if !authorized {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let gauges = metrics::Gauges {
+        rooms: state.ws_state.get_room_count(),
+        users: state.ws_state.get_connected_count(),
+    };
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(gauges),
+    )
+        .into_response()
+}
@@ -1,9 +1,31 @@
+use axum::middleware;
 use axum::Router;
 
+use crate::rate_limit;
 use crate::server_state::ServerState;
 
+mod account;
+mod admin;
+mod control;
+mod events;
+mod metrics;
+mod oidc;
 mod room;
+pub mod templates;
 
 pub fn routes(state: ServerState) -> Router {
-    Router::new().nest("/room", room::routes(state))
+    let limiter = &state.rate_limits.http;
+    Router::new()
+        .nest(
+            "/room",
+            room::routes(state.clone())
+                .merge(control::routes(state.clone()))
+                .merge(events::routes(state.clone())),
+        )
+        .nest("/account", account::routes(state.clone()))
+        .nest("/auth/oidc", oidc::routes(state.clone()))
+        .nest("/admin", admin::routes(state.clone()))
+        .route_layer(middleware::from_fn_with_state(limiter, rate_limit::limit))
+        // Scrapers often share an address with users, they shouldn't eat up their quota.
+        .merge(metrics::routes(state))
 }
@@ -0,0 +1,155 @@
+use axum::extract::Query;
+use axum::extract::State;
+use axum::middleware;
+use axum::response::IntoResponse;
+use axum::response::Redirect;
+use axum::routing::get;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+use http::Uri;
+use serde::Deserialize;
+use serde::Serialize;
+use tower_cookies::cookie::time::Duration;
+use tower_cookies::cookie::time::OffsetDateTime;
+use tower_cookies::cookie::SameSite;
+use tower_cookies::Cookie;
+use tower_cookies::Cookies;
+
+use crate::oidc::{Oidc, OidcError, LOGIN_COOKIE, LOGIN_EXPIRATION, SSO_COOKIE, SSO_EXPIRATION};
+use crate::rate_limit;
+use crate::server_state::ServerState;
+
+const LOGIN_COOKIE_PATH: &str = "/auth/oidc";
+
+#[derive(Debug, Deserialize)]
+struct LoginQuery {
+    /// Where to go once signed in, a path on this server.
+    #[serde(default)]
+    return_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackQuery {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    state: Option<String>,
+    /// Set by the issuer instead of the code when the login failed.
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SsoInfo {
+    name: String,
+    email: Option<String>,
+}
+
+pub(super) fn routes(server_state: ServerState) -> Router {
+    let limit_login =
+        middleware::from_fn_with_state(&server_state.rate_limits.login, rate_limit::limit);
+    Router::new()
+        .route("/login", get(login).route_layer(limit_login.clone()))
+        .route("/callback", get(callback).route_layer(limit_login))
+        .route("/logout", post(logout))
+        .route("/me", get(me))
+        .with_state(server_state)
+}
+
+fn oidc(state: &ServerState) -> Result<&'static Oidc, OidcError> {
+    state.oidc.ok_or(OidcError::Disabled)
+}
+
+/// Only paths of this server, anything else would make us an open redirect. Browsers drop tabs
+/// and newlines from urls and read `\\` as `/`, so `/\t/evil.com` would leave the site as well.
+fn local_path(return_to: Option<String>) -> String {
+    return_to
+        .filter(|path| {
+            let local = path
+                .parse::<Uri>()
+                .is_ok_and(|uri| uri.scheme().is_none() && uri.authority().is_none());
+            local
+                && path.starts_with('/')
+                && !path.starts_with("//")
+                && !path
+                    .bytes()
+                    .any(|b| b.is_ascii_control() || b.is_ascii_whitespace() || b == b'\\')
+        })
+        .unwrap_or_else(|| "/".into())
+}
+
+async fn login(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    Query(query): Query<LoginQuery>,
+) -> Result<Redirect, OidcError> {
+    let oidc = oidc(&state)?;
+    let (login_state, url) = oidc.start_login(local_path(query.return_to)).await?;
+
+    // Lax, the issuer sends the browser back with a top level navigation.
+    let mut login_cookie = Cookie::new(LOGIN_COOKIE, login_state);
+    login_cookie.set_path(LOGIN_COOKIE_PATH);
+    login_cookie
+        .set_expires(OffsetDateTime::now_utc() + Duration::milliseconds(LOGIN_EXPIRATION as i64));
+    login_cookie.set_http_only(true);
+    login_cookie.set_same_site(SameSite::Lax);
+    cookies.add(login_cookie);
+    Ok(Redirect::to(&url))
+}
+
+async fn callback(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    Query(query): Query<CallbackQuery>,
+) -> Result<Redirect, OidcError> {
+    let oidc = oidc(&state)?;
+    let login_state = cookies
+        .get(LOGIN_COOKIE)
+        .map(|cookie| cookie.value().to_owned());
+    let mut login_cookie = Cookie::named(LOGIN_COOKIE);
+    login_cookie.set_path(LOGIN_COOKIE_PATH);
+    cookies.remove(login_cookie);
+
+    if let Some(error) = query.error {
+        log::info!("oidc: the login failed at the issuer: {}", error);
+        return Err(OidcError::Denied);
+    }
+    let (Some(code), Some(query_state)) = (query.code, query.state) else {
+        return Err(OidcError::BadLogin);
+    };
+    // The state must be the one of a login this browser started, not one it was handed.
+    if login_state.as_deref() != Some(query_state.as_str()) {
+        return Err(OidcError::BadLogin);
+    }
+
+    let (session, return_to) = oidc.finish_login(&query_state, &code).await?;
+    log::info!("oidc: {} signed in", session.user);
+    let mut sso_cookie = Cookie::new(SSO_COOKIE, session.encode(&state.ws_state.keys));
+    sso_cookie.set_path("/");
+    sso_cookie
+        .set_expires(OffsetDateTime::now_utc() + Duration::milliseconds(SSO_EXPIRATION as i64));
+    sso_cookie.set_http_only(true);
+    sso_cookie.set_same_site(SameSite::Lax);
+    cookies.add(sso_cookie);
+    Ok(Redirect::to(&return_to))
+}
+
+async fn logout(cookies: Cookies) -> impl IntoResponse {
+    let mut sso_cookie = Cookie::named(SSO_COOKIE);
+    sso_cookie.set_path("/");
+    cookies.remove(sso_cookie);
+    http::StatusCode::NO_CONTENT
+}
+
+async fn me(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+) -> Result<Json<SsoInfo>, OidcError> {
+    oidc(&state)?;
+    let session = state.sso_session(&cookies).ok_or(OidcError::NotSignedIn)?;
+    Ok(Json(SsoInfo {
+        name: session.name,
+        email: session.email,
+    }))
+}
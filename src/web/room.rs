@@ -1,19 +1,19 @@
 use std::net::SocketAddr;
-use std::str::FromStr;
 
-use axum::extract::ConnectInfo;
 use axum::extract::Path;
+use axum::extract::Query;
 use axum::extract::State;
 use axum::headers;
+use axum::middleware;
+use axum::response::Html;
 use axum::response::IntoResponse;
-use axum::response::Response;
 use axum::routing::get;
 use axum::routing::post;
 use axum::Json;
 use axum::Router;
 use axum::TypedHeader;
-use http::header;
 use http::StatusCode;
+use minijinja::context;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -24,16 +24,26 @@ use serde::Serialize;
 };*/
 use tower_cookies::cookie::time::Duration;
 use tower_cookies::cookie::time::OffsetDateTime;
+use tower_cookies::cookie::SameSite;
 use tower_cookies::Cookie;
 use tower_cookies::Cookies;
 
+use crate::accounts::{Account, AccountError, Visit};
+use crate::basic_auth::new_device_secret;
 use crate::basic_auth::OwnerAuth;
 use crate::basic_auth::CHECKED_AUTH_EXPIRATION;
+use crate::basic_auth::DEVICE_COOKIE;
+use crate::basic_auth::DEVICE_EXPIRATION;
 use crate::basic_auth::EXPIRATION;
 use crate::basic_auth::OWNER_AUTH_CHECKED_COOKIE;
 use crate::basic_auth::OWNER_AUTH_COOKIE;
-use crate::common::{utils, Id};
+use crate::client_addr::ClientAddr;
+use crate::common::validation::{self, ValidationErrors, MAX_ROOM_NAME_LEN};
+use crate::common::{utils, RoomId};
+use crate::oidc::{Oidc, OidcError};
+use crate::rate_limit;
 use crate::server_state::ServerState;
+use crate::ws_handler::ws_state::{MAX_SPECTATORS, MAX_USERS};
 use crate::ws_handler::PlayerType;
 use crate::ws_handler::VideoData;
 use crate::ws_handler::PERMISSION_CONTROLLABLE;
@@ -49,24 +59,40 @@ struct CreateRoomPayload {
     video_url: String,
     cc_url: String,
     max_users: i32,
+    #[serde(default)]
+    max_spectators: u32,
     global_control: bool,
     player_index: PlayerType,
+    /// Also give the room a random code like `BLUE-TIGER-42`.
+    #[serde(default)]
+    short_code: bool,
+    /// Also give the room this name in its link, ignored when empty.
+    #[serde(default)]
+    slug: Option<String>,
 }
 
 #[derive(Serialize)]
 struct Room {
     id: String,
     ws_path: String,
+    short_code: Option<String>,
+    slug: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct JoinRoomPayload {
-    room_id: Id,
+    /// The id, short code or slug of the room.
+    room_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JoinDirectQuery {
+    spectate: Option<String>,
 }
 
 #[derive(Serialize)]
 struct JoinUser {
-    room_id: Id,
+    room_id: RoomId,
     name: String,
     ws_path: String,
     auto_connect: bool,
@@ -74,14 +100,21 @@ struct JoinUser {
 
 pub(super) fn routes(server_state: ServerState) -> Router {
     Router::new()
-        .route("/create", post(create))
+        .route(
+            "/create",
+            post(create).route_layer(middleware::from_fn_with_state(
+                &server_state.rate_limits.room_create,
+                rate_limit::limit,
+            )),
+        )
         .route("/join", post(join))
         .route("/:id", get(join_direct))
+        .route("/:id/refresh", post(refresh))
         .with_state(server_state)
 }
 
 /*
-fn parse_uuid_from_base64(id: String) -> Result<Id, impl IntoResponse> {
+fn parse_uuid_from_base64(id: String) -> Result<RoomId, impl IntoResponse> {
     fn format_error<T: Display>(error: T) -> String {
         format!("Error: {}", error)
     }
@@ -90,7 +123,7 @@ fn parse_uuid_from_base64(id: String) -> Result<Id, impl IntoResponse> {
         .as_deref()
         .map_err(format_error)
         .and_then(|id_bytes| std::str::from_utf8(id_bytes).map_err(format_error))
-        .and_then(|id_str| Id::from_str(id_str).map_err(format_error));
+        .and_then(|id_str| RoomId::from_str(id_str).map_err(format_error));
 
     match decode_res {
         Err(e) => {
@@ -122,21 +155,94 @@ fn parse_uuid_from_base64(id: String) -> Result<Id, impl IntoResponse> {
     }
 }*/
 
-async fn validate_cookie(
-    cookies: Cookies,
-    user_agent: String,
-    room_id: &Id,
+/// The secret of the browser, browsers without one are given a new one.
+fn device_secret(cookies: &Cookies) -> String {
+    let secret = cookies
+        .get(DEVICE_COOKIE)
+        .map(|cookie| cookie.value().to_owned())
+        .filter(|secret| !secret.is_empty())
+        .unwrap_or_else(new_device_secret);
+    let mut device_cookie = Cookie::new(DEVICE_COOKIE, secret.clone());
+    device_cookie
+        .set_expires(OffsetDateTime::now_utc() + Duration::milliseconds(DEVICE_EXPIRATION as i64));
+    device_cookie.set_http_only(true);
+    device_cookie.set_same_site(SameSite::Lax);
+    cookies.add(device_cookie);
+    secret
+}
+
+fn set_owner_cookie(cookies: &Cookies, auth: &OwnerAuth, state: &ServerState) {
+    let mut owner_auth_cookie = Cookie::new(OWNER_AUTH_COOKIE, auth.encode(&state.ws_state.keys));
+    owner_auth_cookie
+        .set_expires(OffsetDateTime::now_utc() + Duration::milliseconds(EXPIRATION as i64));
+    owner_auth_cookie.set_http_only(true);
+    // Lax, owners following a link to their room are still recognized.
+    owner_auth_cookie.set_same_site(SameSite::Lax);
+    cookies.add(owner_auth_cookie);
+}
+
+/// A new owner token for the browser, if it's logged in with the account which created the room.
+fn account_owner_auth(
+    cookies: &Cookies,
+    account: Account,
+    room_id: &RoomId,
+    state: &ServerState,
+) -> Option<OwnerAuth> {
+    let room = state.ws_state.get_room(*room_id).ok()?;
+    let key = account.key();
+    if room.get_owner_account() != Some(key.as_str()) {
+        return None;
+    }
+    let auth = OwnerAuth::new(
+        account.display_name,
+        *room_id,
+        &device_secret(cookies),
+        Some(key),
+        utils::get_elapsed_milis() + EXPIRATION,
+    );
+    set_owner_cookie(cookies, &auth, state);
+    Some(auth)
+}
+
+/// Puts the room in the history of the account the browser is logged in with, if any. It's
+/// written in the background, pages don't wait for the disk.
+fn record_visit(
+    state: &ServerState,
+    account: Option<String>,
+    room_id: &RoomId,
+    name: &str,
+    owner: bool,
+) {
+    let (Some(accounts), Some(account)) = (state.accounts, account) else {
+        return;
+    };
+    let visit = Visit::new(room_id.to_string(), name.to_owned(), owner);
+    // Failures are logged by the store, the visit just goes missing.
+    tokio::task::spawn_blocking(move || accounts.record_visit(&account, visit));
+}
+
+/// The owner token of the browser for the room, refreshed once half of its life is over.
+async fn owner_auth(
+    cookies: &Cookies,
+    room_id: &RoomId,
     state: &ServerState,
     addr: &SocketAddr,
-) -> bool {
-    let auth = match cookies.get(OWNER_AUTH_COOKIE) {
+) -> Option<OwnerAuth> {
+    let device_secret = cookies.get(DEVICE_COOKIE);
+    let account = state
+        .session_account(cookies)
+        .await
+        .filter(|account| !account.banned);
+    let account_key = account.as_ref().map(Account::key);
+    let mut auth = match cookies.get(OWNER_AUTH_COOKIE) {
         Some(cookie) => match OwnerAuth::from_token(cookie.value(), &state.ws_state.keys) {
             Err(e) => {
-                println!("OwnerAuth Error: {}", e);
+                log::debug!("room={} peer={} OwnerAuth error: {}", room_id, addr, e);
                 None
             }
             Ok(auth) => {
-                if !auth.is_valid_room_id(addr.ip(), &user_agent, room_id) {
+                let device_secret = device_secret.as_ref().map(Cookie::value);
+                if !auth.is_valid_room_id(device_secret, account_key.as_deref(), room_id) {
                     None
                 } else {
                     Some(auth)
@@ -145,8 +251,29 @@ async fn validate_cookie(
         },
         None => None,
     };
+    // The account which created the room owns it from any browser it logs in from.
+    if let (None, Some(account)) = (&auth, account) {
+        auth = account_owner_auth(cookies, account, room_id, state);
+    }
 
-    if let Some(auth) = auth {
+    // Owners coming back, or staying around, keep their room for as long as it lives.
+    auth.map(|auth| {
+        if !auth.needs_refresh() {
+            return auth;
+        }
+        let auth = auth.refreshed();
+        set_owner_cookie(cookies, &auth, state);
+        auth
+    })
+}
+
+async fn validate_cookie(
+    cookies: Cookies,
+    room_id: &RoomId,
+    state: &ServerState,
+    addr: &SocketAddr,
+) -> bool {
+    if let Some(auth) = owner_auth(&cookies, room_id, state, addr).await {
         let checked_id = state.ws_state.add_checked_auth(auth).await;
         let mut checked_auth_cookie =
             Cookie::new(OWNER_AUTH_CHECKED_COOKIE, checked_id.to_string());
@@ -154,6 +281,8 @@ async fn validate_cookie(
             OffsetDateTime::now_utc() + Duration::milliseconds(CHECKED_AUTH_EXPIRATION as i64),
         );
         checked_auth_cookie.set_http_only(true);
+        // Only the room page opens the WebSocket, other sites never get to send it along.
+        checked_auth_cookie.set_same_site(SameSite::Strict);
         cookies.add(checked_auth_cookie);
         true
     } else {
@@ -165,19 +294,55 @@ async fn validate_cookie(
 async fn create(
     cookies: Cookies,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<ServerState>,
     Json(create_room_payload): Json<CreateRoomPayload>,
 ) -> Result<Json<Room>, impl IntoResponse> {
-    let user_agent = if let Some(TypedHeader(user_agent)) = user_agent {
-        user_agent.to_string()
-    } else {
+    if user_agent.is_none() {
         return Err((StatusCode::FORBIDDEN, "Unknown User agent").into_response());
-    };
+    }
 
-    if create_room_payload.player_index > PLAYER_MAX {
-        return Err((StatusCode::BAD_REQUEST, "Player Index out of bounds.").into_response());
+    let account = state.session_account(&cookies).await;
+    if account.as_ref().is_some_and(|account| account.banned) {
+        return Err(AccountError::Banned.into_response());
+    }
+    if state.oidc.is_some_and(Oidc::require_for_create) && state.sso_session(&cookies).is_none() {
+        return Err(OidcError::Required.into_response());
+    }
+
+    let mut errors = ValidationErrors::default();
+    errors.check(
+        "name",
+        validation::check_name(&create_room_payload.name, MAX_ROOM_NAME_LEN),
+    );
+    errors.check(
+        "creator_name",
+        validation::check_user_name(&create_room_payload.creator_name),
+    );
+    errors.check(
+        "video_url",
+        validation::check_url(&create_room_payload.video_url),
+    );
+    if !create_room_payload.cc_url.is_empty() {
+        errors.check("cc_url", validation::check_url(&create_room_payload.cc_url));
     }
+    errors.check(
+        "max_users",
+        validation::check_range(create_room_payload.max_users.into(), 1, MAX_USERS),
+    );
+    errors.check(
+        "max_spectators",
+        validation::check_range(create_room_payload.max_spectators.into(), 0, MAX_SPECTATORS),
+    );
+    errors.check(
+        "player_index",
+        validation::check_range(
+            create_room_payload.player_index.into(),
+            0,
+            PLAYER_MAX.into(),
+        ),
+    );
+    errors.into_result().map_err(IntoResponse::into_response)?;
+
     let mut data = VideoData::new(
         create_room_payload.video_url,
         create_room_payload.cc_url,
@@ -188,50 +353,69 @@ async fn create(
         data.set_permission(PERMISSION_CONTROLLABLE)
     }
 
-    let max_users = create_room_payload.max_users.abs() as u32;
-    let (id, ws_path) = state
+    // Logged in owners are shown with the name of their account, like everywhere else.
+    let (creator_name, account) = match account {
+        Some(account) => (account.display_name.clone(), Some(account.key())),
+        None => (create_room_payload.creator_name, None),
+    };
+
+    // Checked to be within 1..=MAX_USERS above.
+    let max_users = create_room_payload.max_users as u32;
+    let slug = create_room_payload
+        .slug
+        .as_deref()
+        .filter(|slug| !slug.is_empty());
+    let (room, ws_path) = state
         .ws_state
-        .create_room(data, create_room_payload.name, max_users)
+        .create_room(
+            data,
+            create_room_payload.name,
+            max_users,
+            create_room_payload.max_spectators,
+            create_room_payload.short_code,
+            slug,
+            account.clone(),
+        )
         .await
         .map_err(|err| err.into_response())?;
+    let id = room.get_id();
 
     let expires = utils::get_elapsed_milis() + EXPIRATION;
     let auth = OwnerAuth::new(
-        create_room_payload.creator_name,
+        creator_name,
         id,
-        addr.ip(),
-        user_agent,
+        &device_secret(&cookies),
+        account.clone(),
         expires,
-    )
-    .encode(&state.ws_state.keys);
-
-    let mut owner_auth_cookie = Cookie::new(OWNER_AUTH_COOKIE, auth);
-    owner_auth_cookie
-        .set_expires(OffsetDateTime::now_utc() + Duration::milliseconds(EXPIRATION as i64));
-    owner_auth_cookie.set_http_only(true);
-    cookies.add(owner_auth_cookie);
+    );
+    set_owner_cookie(&cookies, &auth, &state);
+    record_visit(&state, account, &id, room.get_name(), true);
 
-    let id = id.to_string();
-    Ok(Json(Room { id, ws_path }))
+    Ok(Json(Room {
+        id: id.to_string(),
+        ws_path,
+        short_code: room.get_short_code().map(str::to_owned),
+        slug: room.get_slug().map(str::to_owned),
+    }))
 }
 
 async fn join(
     cookies: Cookies,
     State(state): State<ServerState>,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    ClientAddr(addr): ClientAddr,
     Json(join_room_payload): Json<JoinRoomPayload>,
 ) -> Result<Json<JoinUser>, impl IntoResponse> {
-    let user_agent = if let Some(TypedHeader(user_agent)) = user_agent {
-        user_agent.to_string()
-    } else {
+    if user_agent.is_none() {
         return Err((StatusCode::FORBIDDEN, "Unknown User agent").into_response());
-    };
+    }
     let (room_id, name, ws_path) = state
         .ws_state
-        .verify_room(join_room_payload.room_id)
+        .verify_room(&join_room_payload.room_id)
         .map_err(|e| e.into_response())?;
-    let auto_connect = validate_cookie(cookies, user_agent, &room_id, &state, &addr).await;
+    let account = state.session_account_key(&cookies).await;
+    let auto_connect = validate_cookie(cookies, &room_id, &state, &addr).await;
+    record_visit(&state, account, &room_id, &name, auto_connect);
     Ok(Json(JoinUser {
         room_id,
         name,
@@ -244,27 +428,38 @@ async fn join_direct(
     cookies: Cookies,
     State(state): State<ServerState>,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    ClientAddr(addr): ClientAddr,
     Path(id): Path<String>,
+    Query(query): Query<JoinDirectQuery>,
 ) -> Result<impl IntoResponse, impl IntoResponse> {
-    let user_agent = if let Some(TypedHeader(user_agent)) = user_agent {
-        user_agent.to_string()
-    } else {
+    if user_agent.is_none() {
         return Err((StatusCode::FORBIDDEN, "Unknown User agent").into_response());
+    }
+
+    let verified = if query.spectate.is_some() {
+        state.ws_state.verify_spectating_room(&id)
+    } else {
+        state.ws_state.verify_room(&id)
     };
+    let (room_id, name, ws_path) = verified.map_err(|e| e.into_response())?;
 
-    let id = Id::from_str(id.as_str())
-        .map_err(|_| (StatusCode::BAD_REQUEST, "Bad Room Id was provided.").into_response())?;
-    let (room_id, name, ws_path) = state
-        .ws_state
-        .verify_room(id)
-        .map_err(|e| e.into_response())?;
+    let account = state.session_account_key(&cookies).await;
+    let auto_connect = validate_cookie(cookies, &room_id, &state, &addr).await;
+    record_visit(&state, account, &room_id, &name, auto_connect);
 
-    let mut file = match tokio::fs::read_to_string(state.get_dyn_dir().join("room-min.html")).await
+    let room = JoinUser {
+        room_id,
+        name,
+        ws_path,
+        auto_connect,
+    };
+    let page = match state
+        .templates
+        .render("room-min.html", context! { room => room })
     {
-        Ok(file) => file,
+        Ok(page) => page,
         Err(err) => {
-            println!("room-min.html: {}", err);
+            log::error!("room={} failed to render room-min.html: {:#}", room_id, err);
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Unexpectedly some required web page caused an error.",
@@ -272,29 +467,30 @@ async fn join_direct(
                 .into_response());
         }
     };
+    Ok(Html(page))
+}
 
-    let auto_connect = validate_cookie(cookies, user_agent, &room_id, &state, &addr).await;
-
-    // this is peak server side html generation ;')
-    file = file
-        .replace(
-            "let room_data = null;",
-            &format!(
-                r#"let room_data = {{
-                room_id: '{}',
-                name: '{}',
-                ws_path: '{}'
-            }};
-            "#,
-                room_id, name, ws_path
-            ),
+/// Refreshes the owner token, the room page calls it while its WebSocket is open since the token
+/// is otherwise only refreshed when the page is loaded.
+async fn refresh(
+    cookies: Cookies,
+    State(state): State<ServerState>,
+    ClientAddr(addr): ClientAddr,
+    Path(id): Path<String>,
+) -> Result<StatusCode, impl IntoResponse> {
+    let room_id = state
+        .ws_state
+        .resolve_room(&id)
+        .map_err(IntoResponse::into_response)?;
+    if owner_auth(&cookies, &room_id, &state, &addr)
+        .await
+        .is_none()
+    {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Only the owner of the room has a token.",
         )
-        .replace(
-            "let autoConnect = false;",
-            &format!("let autoConnect = {};", auto_connect),
-        );
-    let header = Response::builder()
-        .header(header::CONTENT_TYPE, "text/html")
-        .body(file);
-    Ok(header.unwrap())
+            .into_response());
+    }
+    Ok(StatusCode::NO_CONTENT)
 }
@@ -0,0 +1,36 @@
+//! Pages rendered on the server from the templates of the dynamic web directory.
+//!
+//! Templates are compiled the first time they're used and kept in memory, debug builds compile
+//! them again on every render so edits show up without a restart.
+
+use std::path::Path;
+use std::sync::RwLock;
+
+use minijinja::{path_loader, Environment};
+use serde::Serialize;
+
+pub struct Templates {
+    env: RwLock<Environment<'static>>,
+}
+
+impl Templates {
+    pub fn new(dir: &Path) -> Self {
+        let mut env = Environment::new();
+        env.set_loader(path_loader(dir));
+        Self {
+            env: RwLock::new(env),
+        }
+    }
+
+    /// Renders the template, values are escaped for html since all of them are `.html` files.
+    pub fn render<S: Serialize>(&self, name: &str, ctx: S) -> Result<String, minijinja::Error> {
+        if cfg!(debug_assertions) {
+            self.env
+                .write()
+                .unwrap_or_else(|err| err.into_inner())
+                .clear_templates();
+        }
+        let env = self.env.read().unwrap_or_else(|err| err.into_inner());
+        env.get_template(name)?.render(ctx)
+    }
+}
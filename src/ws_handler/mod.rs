@@ -1,14 +1,16 @@
 use crate::common::utils::get_elapsed_milis;
 
+mod room_alias;
 pub mod room_state;
-mod user_state;
+pub(crate) mod user_state;
 pub mod ws_state;
 
-pub use user_state::validate_and_handle_client;
+pub use user_state::{check_str_packet, validate_and_handle_client};
 
-pub(super) type WSMsgSender =
-    tokio::sync::mpsc::UnboundedSender<crate::sturdy_ws::WebSocketMessage>;
-pub(super) type BMsgSender = tokio::sync::broadcast::Sender<std::sync::Arc<[u8]>>;
+pub(super) type WSMsgSender = tokio::sync::mpsc::Sender<crate::sturdy_ws::WebSocketMessage>;
+pub(super) type BMsgSender = tokio::sync::broadcast::Sender<crate::sturdy_ws::PreparedMessage>;
+pub type BMsgSendError =
+    tokio::sync::broadcast::error::SendError<crate::sturdy_ws::PreparedMessage>;
 
 // TODO: Refactor these parts?
 pub type StateType = u8;
@@ -32,8 +34,34 @@ pub const PLAYER_MAX: PlayerType = PLAYER_NORMAL;
 
 pub const CLIENT_TIMEOUT: u64 = 60 * 2 * 1000; // 2 Minutes
 pub const SYNC_TIMEOUT: u128 = 5 * 1000; // 5 seconds
+/// Capacity of each user's direct message queue, a user falling this far behind is stuck.
+pub const DM_QUEUE_CAPACITY: usize = 32;
+/// How long a close frame waits for room in a full direct message queue, the connection is
+/// dropped without one after that.
+pub const CLOSE_QUEUE_TIMEOUT: u64 = 2 * 1000; // 2 seconds
+/// How long a client we closed gets to answer with its own close frame before it's dropped.
+pub const CLOSE_REPLY_TIMEOUT: u64 = 3 * 1000; // 3 seconds
+/// Default cap of the bytes buffered for a socket which can't keep up, it gets disconnected
+/// once it's exceeded. Should be higher than the 128KiB write buffer of the socket.
+pub const DEFAULT_MAX_WRITE_BUFFER: usize = 1024 * 1024;
 pub const MAX_VIDEO_LEN: usize = 4 * 3600 * 1000; // 4 hours
 
+/// Converts a video position in seconds, as the clients send it, into miliseconds.
+///
+/// Returns `None` if the position is negative, not a number or past [`MAX_VIDEO_LEN`].
+#[inline]
+pub fn video_time_from_secs(secs: f32) -> Option<usize> {
+    // Those would otherwise saturate to 0 in the cast.
+    if secs.is_nan() || secs < 0.0 {
+        return None;
+    }
+    let time = (secs * 1000f32).floor() as usize;
+    if time > MAX_VIDEO_LEN {
+        return None;
+    }
+    Some(time)
+}
+
 #[derive(Clone, Copy)]
 // TODO: Remove permission from User
 pub struct Permission(PermissionType);
@@ -48,7 +76,7 @@ impl Permission {
     }
 
     pub fn clear_permission(&mut self, permission: PermissionType) {
-        self.0 = self.0 & !permission
+        self.0 &= !permission
     }
 }
 
@@ -145,7 +173,7 @@ impl VideoData {
 
     pub fn update_time(&mut self) {
         let current_time = get_elapsed_milis();
-        let diff = current_time - self.last_time_updated;
+        let diff = current_time.saturating_sub(self.last_time_updated);
         self.last_time_updated = current_time;
         if self.state == STATE_PLAY {
             self.time += diff as usize;
@@ -0,0 +1,60 @@
+//! Human friendly aliases of room ids, short codes like `BLUE-TIGER-42` which are easy to read
+//! out loud, and slugs chosen by the room owner.
+//!
+//! Both share the same namespace, looked up without caring about the case.
+
+use rand::Rng;
+
+const COLORS: [&str; 32] = [
+    "AMBER", "AQUA", "BEIGE", "BLACK", "BLUE", "BRONZE", "BROWN", "CORAL", "CREAM", "CYAN", "GOLD",
+    "GRAY", "GREEN", "IVORY", "JADE", "KHAKI", "LEMON", "LILAC", "LIME", "MINT", "NAVY", "OLIVE",
+    "ORANGE", "PEACH", "PINK", "PLUM", "PURPLE", "RED", "RUBY", "SILVER", "TEAL", "WHITE",
+];
+
+const ANIMALS: [&str; 64] = [
+    "BADGER", "BEAR", "BEAVER", "BISON", "CAMEL", "CAT", "CHEETAH", "COBRA", "CRAB", "CRANE",
+    "CROW", "DEER", "DOLPHIN", "DOVE", "DRAGON", "EAGLE", "EEL", "ELK", "FALCON", "FERRET", "FOX",
+    "FROG", "GECKO", "GOAT", "GOOSE", "HAWK", "HERON", "HIPPO", "HORSE", "IBIS", "JAGUAR", "KOALA",
+    "LEMUR", "LION", "LLAMA", "LYNX", "MOLE", "MOOSE", "MOTH", "MOUSE", "OTTER", "OWL", "PANDA",
+    "PANTHER", "PARROT", "PENGUIN", "PUMA", "RABBIT", "RAVEN", "SEAL", "SHARK", "SLOTH", "SNAKE",
+    "SPIDER", "SQUID", "SWAN", "TIGER", "TOAD", "TURTLE", "VIPER", "WALRUS", "WHALE", "WOLF",
+    "ZEBRA",
+];
+
+pub(super) const MIN_SLUG_LEN: usize = 3;
+pub(super) const MAX_SLUG_LEN: usize = 32;
+
+/// Paths under `/room/` which a slug would shadow.
+const RESERVED_SLUGS: [&str; 3] = ["create", "join", "ws"];
+
+/// A random code in the form of `COLOR-ANIMAL-NN`.
+pub(super) fn new_short_code() -> String {
+    let mut rng = rand::thread_rng();
+    format!(
+        "{}-{}-{}",
+        COLORS[rng.gen_range(0..COLORS.len())],
+        ANIMALS[rng.gen_range(0..ANIMALS.len())],
+        rng.gen_range(10..100)
+    )
+}
+
+/// Normalizes a slug chosen by an owner, returns `None` if it can't be used.
+///
+/// Slugs are lowercase ascii letters, digits and inner dashes.
+pub(super) fn normalize_slug(slug: &str) -> Option<String> {
+    let slug = alias_key(slug);
+    let valid = (MIN_SLUG_LEN..=MAX_SLUG_LEN).contains(&slug.len())
+        && slug
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !slug.starts_with('-')
+        && !slug.ends_with('-')
+        && !RESERVED_SLUGS.contains(&slug.as_str());
+    valid.then_some(slug)
+}
+
+/// The key an alias is stored under.
+#[inline]
+pub(super) fn alias_key(alias: &str) -> String {
+    alias.trim().to_ascii_lowercase()
+}
@@ -1,21 +1,30 @@
 use std::sync::atomic::{AtomicU32, Ordering};
 
 use tokio;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 
-use crate::common::Id;
+use crate::common::RoomId;
+use crate::sturdy_ws::{PreparedMessage, WebSocketMessage};
 
+use super::user_state::{video_data_json, StringPacket};
 use super::ws_state::WsState;
-use super::VideoData;
-use super::{BMsgSender, CLIENT_TIMEOUT};
+use super::{BMsgSendError, BMsgSender, CLIENT_TIMEOUT};
+use super::{PlayerType, VideoData, STATE_PAUSE, STATE_PLAY};
 
 pub struct RoomState {
     pub(super) data: RwLock<VideoData>,
-    pub(super) id: Id,
+    pub(super) id: RoomId,
     pub(super) name: String,
+    /// Aliases resolving to this room, released along with it.
+    pub(super) short_code: Option<String>,
+    pub(super) slug: Option<String>,
+    /// The account which created the room, it owns the room from any browser.
+    pub(super) owner_account: Option<String>,
     pub(super) broadcast_tx: BMsgSender,
     pub(super) remaining_users: AtomicU32,
     pub(super) max_users: u32,
+    pub(super) remaining_spectators: AtomicU32,
+    pub(super) max_spectators: u32,
 }
 
 impl RoomState {
@@ -25,7 +34,7 @@ impl RoomState {
                 .store(self.max_users, Ordering::Release);
             return false;
         }
-        return true;
+        true
     }
 
     pub(super) fn user_join(&self) -> bool {
@@ -33,25 +42,150 @@ impl RoomState {
             self.remaining_users.store(0, Ordering::Release);
             return false;
         }
-        return true;
+        true
+    }
+
+    pub(super) fn spectator_left(&self) {
+        if self.remaining_spectators.fetch_add(1, Ordering::AcqRel) >= self.max_spectators {
+            self.remaining_spectators
+                .store(self.max_spectators, Ordering::Release);
+        }
+    }
+
+    /// Spectators have their own capacity, they never take away a slot from the users.
+    pub(super) fn spectator_join(&self) -> bool {
+        if self.remaining_spectators.fetch_sub(1, Ordering::AcqRel) == 0 {
+            self.remaining_spectators.store(0, Ordering::Release);
+            return false;
+        }
+        true
     }
 
     pub(super) fn is_full(&self) -> bool {
         self.remaining_users.load(Ordering::Relaxed) == 0
     }
 
+    pub(super) fn is_full_of_spectators(&self) -> bool {
+        self.remaining_spectators.load(Ordering::Relaxed) == 0
+    }
+
     pub(super) fn is_empty(&self) -> bool {
         self.remaining_users.load(Ordering::Relaxed) == self.max_users
     }
+
+    #[inline]
+    pub fn get_id(&self) -> RoomId {
+        self.id
+    }
+
+    #[inline]
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn get_short_code(&self) -> Option<&str> {
+        self.short_code.as_deref()
+    }
+
+    #[inline]
+    pub fn get_slug(&self) -> Option<&str> {
+        self.slug.as_deref()
+    }
+
+    #[inline]
+    pub fn get_owner_account(&self) -> Option<&str> {
+        self.owner_account.as_deref()
+    }
+
+    #[inline]
+    pub fn get_max_users(&self) -> u32 {
+        self.max_users
+    }
+
+    #[inline]
+    pub fn get_user_count(&self) -> u32 {
+        self.max_users
+            .saturating_sub(self.remaining_users.load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    pub fn get_max_spectators(&self) -> u32 {
+        self.max_spectators
+    }
+
+    #[inline]
+    pub fn get_spectator_count(&self) -> u32 {
+        self.max_spectators
+            .saturating_sub(self.remaining_spectators.load(Ordering::Relaxed))
+    }
+
+    /// Brings the video time up to date and gives `reader` access to the video data.
+    pub async fn read_data<R, F: FnOnce(&VideoData) -> R>(&self, reader: F) -> R {
+        let mut data = self.data.write().await;
+        data.update_time();
+        reader(&data)
+    }
+
+    /// Serializes the up to date video data without any user specific permission.
+    pub async fn get_video_data_json(&self) -> String {
+        self.read_data(|data| video_data_json(data, None)).await
+    }
+
+    /// Listens to everything broadcasted to the room, without joining it.
+    #[inline]
+    pub fn subscribe(&self) -> broadcast::Receiver<PreparedMessage> {
+        self.broadcast_tx.subscribe()
+    }
+
+    pub async fn play(&self, time: usize) -> Result<(), BMsgSendError> {
+        self.data.write().await.set_state(time, STATE_PLAY);
+        self.broadcast(StringPacket::new("play").arg(time.to_string()))
+    }
+
+    pub async fn pause(&self, time: usize) -> Result<(), BMsgSendError> {
+        self.data.write().await.set_state(time, STATE_PAUSE);
+        self.broadcast(StringPacket::new("pause").arg(time.to_string()))
+    }
+
+    pub async fn seek(&self, time: usize) -> Result<(), BMsgSendError> {
+        self.data.write().await.set_time(time);
+        self.broadcast(StringPacket::new("seek").arg(time.to_string()))
+    }
+
+    /// Replaces the room's video, the new video starts paused from the beginning.
+    pub async fn change_video(
+        &self,
+        url: String,
+        cc_url: String,
+        current_player: PlayerType,
+    ) -> Result<(), BMsgSendError> {
+        let packet = {
+            let mut data = self.data.write().await;
+            data.update_url(url);
+            data.update_cc_url(cc_url);
+            data.set_current_player(current_player);
+            data.set_state(0, STATE_PAUSE);
+            StringPacket::new("video_data").arg(video_data_json(&data, None))
+        };
+        self.broadcast(packet)
+    }
+
+    #[inline]
+    fn broadcast(&self, packet: StringPacket) -> Result<(), BMsgSendError> {
+        let msg: WebSocketMessage = packet.into();
+        self.broadcast_tx.send(msg.prepare()).map(|_| ())
+    }
 }
 
-pub(super) async fn room_shutdown_gracefully(room_id: Id, ws_state: &'static WsState) {
+pub(super) async fn room_shutdown_gracefully(room_id: RoomId, ws_state: &'static WsState) {
     tokio::time::sleep(std::time::Duration::from_millis(CLIENT_TIMEOUT)).await;
+    // Someone joined back meanwhile, or the room is already gone.
     let res = ws_state.rooms.read(&room_id, |_, v| v.is_empty());
-    if matches!(res, Some(true)) {
+    if !matches!(res, Some(true)) {
         return;
     }
 
-    println!("[ROOM UPDATE] Removing room id: {}", room_id);
-    ws_state.rooms.remove_async(&room_id).await;
+    log::info!("room={} removing the room", room_id);
+    ws_state.remove_room(room_id).await;
 }
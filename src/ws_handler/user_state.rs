@@ -5,24 +5,35 @@ use std::{
     ops::{ControlFlow, Deref},
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 
-use futures_util::StreamExt;
+use futures_util::{lock::BiLock, ready, SinkExt, StreamExt};
 use serde_json::json;
 use thiserror::Error;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::{broadcast::error::RecvError, mpsc, Notify, RwLock};
 
 use super::{
-    room_state::RoomState, ws_state::WsState, Permission, PermissionType, StateType, VideoData,
-    WSMsgSender, MAX_VIDEO_LEN, PERMISSION_ALL, PERMISSION_CONTROLLABLE, STATE_MAX,
+    room_state::RoomState, video_time_from_secs, ws_state::WsState, Permission, PermissionType,
+    StateType, VideoData, WSMsgSender, PERMISSION_ALL, PERMISSION_CONTROLLABLE, STATE_MAX,
 };
 use crate::{
+    accounts::Account,
     basic_auth::OwnerAuth,
-    common::Id,
-    sturdy_ws::{ws_stream::SplitStream, CloseFrame, Message, WebSocket, WebSocketMessage},
+    common::{
+        utils::Redacted,
+        validation::{self, InvalidField},
+        RoomId, UserId,
+    },
+    metrics::{self, Handler},
+    rate_limit::RateLimiter,
+    sturdy_ws::{
+        ws_stream::SplitStream, CloseCode, CloseFrame, Message, WebSocket, WebSocketMessage,
+    },
     ws_handler::{
-        room_state::room_shutdown_gracefully, ws_state::WebSocketStateError, CLIENT_TIMEOUT,
-        STATE_PAUSE, STATE_PLAY, SYNC_TIMEOUT,
+        room_state::room_shutdown_gracefully,
+        ws_state::{Disconnect, WebSocketStateError},
+        CLIENT_TIMEOUT, CLOSE_QUEUE_TIMEOUT, CLOSE_REPLY_TIMEOUT, DM_QUEUE_CAPACITY, SYNC_TIMEOUT,
     },
 };
 
@@ -34,8 +45,12 @@ enum ValidationError {
     NoRoom,
     #[error("The specified room is full.")]
     RoomFull,
+    #[error("The specified room can't take any more spectators.")]
+    SpectatorsFull,
     #[error("Invalid packet structure.")]
     InvalidPacket,
+    #[error("The name {0}.")]
+    InvalidName(#[from] InvalidField),
 }
 
 impl From<WebSocketStateError> for ValidationError {
@@ -43,22 +58,60 @@ impl From<WebSocketStateError> for ValidationError {
         match err {
             WebSocketStateError::NoRoom => ValidationError::NoRoom,
             WebSocketStateError::RoomFull => ValidationError::RoomFull,
+            WebSocketStateError::SpectatorsFull => ValidationError::SpectatorsFull,
             _ => ValidationError::InvalidPacket,
         }
     }
 }
 
 #[derive(Clone)]
-#[allow(unused)]
 pub(super) struct UserState {
-    pub id: Id,
+    pub id: UserId,
     pub tx: WSMsgSender,
+    pub name: String,
+    pub room_id: RoomId,
+    pub addr: SocketAddr,
+    pub spectator: bool,
+    /// Normalized username of the account the user logged in with.
+    pub account: Option<String>,
+    /// Aborts the connection's tasks, for when a close frame can't be queued.
+    pub(super) drop_connection: Arc<Notify>,
+}
+
+impl UserState {
+    /// Queues a close frame, waiting up to [`CLOSE_QUEUE_TIMEOUT`] for room in the queue, and
+    /// drops the connection if there is none by then.
+    pub(super) async fn close(&self, code: CloseCode, reason: &'static str) -> Disconnect {
+        let msg = WebSocketMessage::Close(Some(CloseFrame {
+            code,
+            reason: Cow::Borrowed(reason),
+        }));
+        let timeout = Duration::from_millis(CLOSE_QUEUE_TIMEOUT);
+        match tokio::time::timeout(timeout, self.tx.send(msg)).await {
+            Ok(Ok(())) => Disconnect::Closed,
+            Ok(Err(_)) => Disconnect::Gone,
+            Err(_) => {
+                log::warn!(
+                    "room={} user={} peer={} didn't take the close frame, dropping it",
+                    self.room_id,
+                    self.id,
+                    self.addr
+                );
+                self.drop_connection.notify_one();
+                Disconnect::Dropped
+            }
+        }
+    }
 }
 
 struct LocalUserState {
     pub name: String,
-    pub id: Id,
+    pub id: UserId,
     pub room_state: Arc<RoomState>,
+    pub addr: SocketAddr,
+    /// Spectators only receive, they aren't announced to the room either.
+    pub spectator: bool,
+    pub account: Option<String>,
 }
 
 pub struct StringPacket {
@@ -87,7 +140,7 @@ impl StringPacket {
     }
 
     pub fn arg<S: AsRef<str>>(mut self, value: S) -> Self {
-        if self.args.len() == 0 {
+        if self.args.is_empty() {
             self.args += value.as_ref();
         } else {
             let _ = write!(&mut self.args, "{}{}", self.arg_sep, value.as_ref());
@@ -96,46 +149,44 @@ impl StringPacket {
     }
 }
 
-impl Into<String> for StringPacket {
-    fn into(self) -> String {
+impl From<StringPacket> for String {
+    fn from(val: StringPacket) -> Self {
         format!(
             "{}{}{}{}",
-            self.header, self.packet_type, self.type_sep, self.args
+            val.header, val.packet_type, val.type_sep, val.args
         )
     }
 }
 
-impl Into<WebSocketMessage> for StringPacket {
-    fn into(self) -> WebSocketMessage {
-        WebSocketMessage::Text(self.into())
+impl From<StringPacket> for WebSocketMessage {
+    fn from(val: StringPacket) -> Self {
+        WebSocketMessage::Text(val.into())
     }
 }
 
-fn video_data_json(data: &VideoData, permission: PermissionType) -> String {
-    json!({
+/// Serializes the video data for the `video_data` packet, `permission` is left out for packets
+/// which are broadcasted to the whole room.
+pub(super) fn video_data_json(data: &VideoData, permission: Option<PermissionType>) -> String {
+    let mut json = json!({
         "url": data.get_url(),
         "cc_url": data.get_cc_url(),
         "time": data.get_time(),
         "state": data.get_state(),
         "current_player": data.get_current_player(),
-        "permission": permission
-    })
-    .to_string()
+    });
+    if let Some(permission) = permission {
+        json["permission"] = permission.into();
+    }
+    json.to_string()
 }
 
 // TODO: Implement something like `StringPacket`.
 #[inline]
-fn check_str_packet<'a>(input_str: &'a str) -> Option<(&'a str, &'a str)> {
-    let Some(input_str) = input_str.strip_prefix("||-=-||") else {
-        return None;
-    };
+pub fn check_str_packet(input_str: &str) -> Option<(&str, &str)> {
+    let input_str = input_str.strip_prefix("||-=-||")?;
     let mut full_data = input_str.split("-=-");
-    let Some(data_type) = full_data.next() else {
-        return None;
-    };
-    let Some(data) = full_data.next() else {
-        return None;
-    };
+    let data_type = full_data.next()?;
+    let data = full_data.next()?;
     Some((data_type, data))
 }
 
@@ -145,84 +196,158 @@ async fn user_handle(
     local_data: LocalUserState,
     permission: Permission,
     ws_state: &'static WsState,
+    packet_limiter: &'static RateLimiter,
 ) {
     let id = local_data.id;
 
-    let (dm_tx, mut dm_rx) = mpsc::unbounded_channel();
+    let name = local_data.name.clone();
+    let current_room_id = local_data.room_state.id;
+    let spectator = local_data.spectator;
+    let account = local_data.account.clone();
+
+    let (dm_tx, mut dm_rx) = mpsc::channel(DM_QUEUE_CAPACITY);
+    let drop_connection = Arc::new(Notify::new());
     let user = UserState {
         id,
         tx: dm_tx.clone(),
+        name: name.clone(),
+        room_id: current_room_id,
+        addr: who,
+        spectator,
+        account,
+        drop_connection: drop_connection.clone(),
     };
     let _ = ws_state.users.insert_async(id, user).await;
-
-    let name = local_data.name.clone();
-    let current_room_id = local_data.room_state.id;
-
-    let msg: WebSocketMessage = StringPacket::new("joined")
-        .arg(name.clone())
-        .arg(id.to_string())
-        .into();
-    let _ = local_data
-        .room_state
-        .broadcast_tx
-        .send(msg.into_server_shared_bytes());
+    log::info!(
+        "room={} user={} peer={} joined the room as {} (name: {})",
+        current_room_id,
+        id,
+        who,
+        if spectator { "spectator" } else { "user" },
+        Redacted(&name)
+    );
+
+    if !spectator {
+        let msg: WebSocketMessage = StringPacket::new("joined")
+            .arg(name.clone())
+            .arg(id.to_string())
+            .into();
+        let _ = local_data.room_state.broadcast_tx.send(msg.prepare());
+    }
 
     {
         let r_data = local_data.room_state.data.read().await;
-        let data_str =
-            StringPacket::new("video_data").arg(video_data_json(r_data.deref(), permission.into()));
-        let _ = dm_tx.send(data_str.into());
+        let data_str = StringPacket::new("video_data")
+            .arg(video_data_json(r_data.deref(), Some(permission.into())));
         drop(r_data);
+        let _ = dm_tx.send(data_str.into()).await;
     }
     let mut broadcast_rx = local_data.room_state.broadcast_tx.subscribe();
+    let room_state = local_data.room_state.clone();
 
     let (socket, receiver) = socket.sock_split();
     let mut send_task = tokio::spawn(async move {
+        let mut pending_writes = false;
         loop {
             let msg = tokio::select! {
+                // Frames the socket couldn't take are flushed as soon as it's writable again.
+                res = flush_pending_writes(&socket), if pending_writes => {
+                    if res.is_err() {
+                        break;
+                    }
+                    pending_writes = false;
+                    continue;
+                },
                 msg = dm_rx.recv() => {
                     let Some(msg) = msg else {
                         break;
                     };
-                    msg.into_server_shared_bytes()
+                    msg.prepare()
                 },
                 msg = broadcast_rx.recv() => {
-                    let Ok(msg) = msg else {
-                        break;
-                    };
-                    msg
+                    match msg {
+                        Ok(msg) => msg,
+                        Err(RecvError::Lagged(skipped)) => {
+                            metrics::broadcast_lagged(skipped);
+                            log::debug!(
+                                "room={} user={} peer={} lagged behind by {} packets, resyncing",
+                                current_room_id,
+                                id,
+                                who,
+                                skipped
+                            );
+                            // Whatever is still queued is outdated as well, skip to the latest
+                            // packets and catch up with a fresh snapshot instead.
+                            broadcast_rx = broadcast_rx.resubscribe();
+                            let data_str = room_state
+                                .read_data(|data| video_data_json(data, Some(permission.into())))
+                                .await;
+                            let msg: WebSocketMessage =
+                                StringPacket::new("video_data").arg(data_str).into();
+                            msg.prepare()
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
                 }
             };
             let mut socket = socket.lock().await;
-            unsafe {
-                // We trust ourselves :")
-                if let Err(err) = socket.send_raw(msg.as_ref()) {
-                    match err {
-                        crate::sturdy_ws::Error::Io(e)
-                            if e.kind() == std::io::ErrorKind::WouldBlock => {}
-                        _ => break,
-                    }
+            if let Err(err) = socket.send_prepared(&msg) {
+                if matches!(err, crate::sturdy_ws::Error::WriteBufferFull2) {
+                    log::warn!(
+                        "room={} user={} peer={} isn't keeping up, write buffer cap exceeded",
+                        current_room_id,
+                        id,
+                        who
+                    );
                 }
+                break;
             }
+            pending_writes = socket.pending_write_len() > 0;
         }
     });
 
     let mut recv_task = if permission.has_permission(PERMISSION_CONTROLLABLE) {
-        tokio::spawn(recv_task_privileged(receiver, dm_tx, local_data))
+        tokio::spawn(recv_task_privileged(
+            receiver,
+            dm_tx,
+            local_data,
+            packet_limiter,
+        ))
     } else {
-        tokio::spawn(recv_task_normal(receiver, dm_tx, local_data, permission))
+        tokio::spawn(recv_task_normal(
+            receiver,
+            dm_tx,
+            local_data,
+            permission,
+            packet_limiter,
+        ))
     };
 
     tokio::select! {
         _ = (&mut recv_task) => send_task.abort(),
         _ = (&mut send_task) => recv_task.abort(),
+        _ = drop_connection.notified() => {
+            send_task.abort();
+            recv_task.abort();
+        }
     }
 
+    ws_state.users.remove_async(&id).await;
+    log::info!(
+        "room={} user={} peer={} left the room",
+        current_room_id,
+        id,
+        who
+    );
+
+    // The room might have been closed by an admin already.
     let Some(room) = ws_state.rooms.read(&current_room_id, |_, v| v.clone()) else {
         return;
     };
 
-    if !room.user_left() {
+    if spectator {
+        room.spectator_left();
+    } else if !room.user_left() {
         room_shutdown_gracefully(current_room_id, ws_state).await;
     } else {
         let msg: WebSocketMessage = StringPacket::new("left")
@@ -230,17 +355,54 @@ async fn user_handle(
             .arg(id.to_string())
             .into();
 
-        let _ = room.broadcast_tx.send(msg.into_server_shared_bytes());
+        let _ = room.broadcast_tx.send(msg.prepare());
     }
+}
 
-    ws_state.users.remove_async(&id).await;
-    println!("{} left! - id: {}", who, id);
+/// Resolves once everything buffered by `send_prepared` is written to the socket, the lock is only
+/// held while polling so the receiving side isn't blocked meanwhile.
+async fn flush_pending_writes(socket: &BiLock<WebSocket>) -> Result<(), axum::Error> {
+    std::future::poll_fn(|cx| {
+        let mut socket = ready!(socket.poll_lock(cx));
+        socket.poll_flush_unpin(cx)
+    })
+    .await
+}
+
+/// Tells the user they're sending packets too fast and waits for the socket to close, the send
+/// task has to stay around long enough to write the close frame. Clients which don't answer are
+/// dropped after [`CLOSE_REPLY_TIMEOUT`].
+async fn close_rate_limited(
+    receiver: &mut SplitStream<WebSocket>,
+    dm_tx: &WSMsgSender,
+    local_data: &LocalUserState,
+) {
+    log::info!(
+        "room={} user={} peer={} sent packets too fast, closing",
+        local_data.room_state.id,
+        local_data.id,
+        local_data.addr
+    );
+    let msg = WebSocketMessage::Close(Some(CloseFrame {
+        code: CloseCode::Policy,
+        reason: Cow::Borrowed("rate limit exceeded"),
+    }));
+    let queue_timeout = Duration::from_millis(CLOSE_QUEUE_TIMEOUT);
+    if !matches!(
+        tokio::time::timeout(queue_timeout, dm_tx.send(msg)).await,
+        Ok(Ok(()))
+    ) {
+        return;
+    }
+    let drain = async { while let Some(Ok(_)) = receiver.next().await {} };
+    let _ = tokio::time::timeout(Duration::from_millis(CLOSE_REPLY_TIMEOUT), drain).await;
 }
 
 async fn recv_task_privileged(
     mut receiver: SplitStream<WebSocket>,
     dm_tx: WSMsgSender,
     mut local_data: LocalUserState,
+    packet_limiter: &'static RateLimiter,
 ) {
     let mut missed_pings = 0;
     loop {
@@ -260,7 +422,17 @@ async fn recv_task_privileged(
         if let Some(msg) = msg {
             match msg {
                 Message::Text(input_str) => {
-                    println!(">>> {} sent str: {:?}", local_data.name, input_str);
+                    log::trace!(
+                        "room={} user={} peer={} received text: {}",
+                        local_data.room_state.id,
+                        local_data.id,
+                        local_data.addr,
+                        Redacted(&input_str)
+                    );
+                    if packet_limiter.check(local_data.addr.ip()).is_err() {
+                        close_rate_limited(&mut receiver, &dm_tx, &local_data).await;
+                        break;
+                    }
                     match process_privileged_message(input_str, &dm_tx, &mut local_data).await {
                         ControlFlow::Break(_) => {
                             // TODO: Print why we're breaking..
@@ -292,6 +464,7 @@ async fn recv_task_normal(
     dm_tx: WSMsgSender,
     local_data: LocalUserState,
     permission: Permission,
+    packet_limiter: &'static RateLimiter,
 ) {
     let mut missed_pings = 0;
     loop {
@@ -311,7 +484,17 @@ async fn recv_task_normal(
         if let Some(msg) = msg {
             match msg {
                 Message::Text(input_str) => {
-                    println!(">>> {} sent str: {:?}", local_data.name, input_str);
+                    log::trace!(
+                        "room={} user={} peer={} received text: {}",
+                        local_data.room_state.id,
+                        local_data.id,
+                        local_data.addr,
+                        Redacted(&input_str)
+                    );
+                    if packet_limiter.check(local_data.addr.ip()).is_err() {
+                        close_rate_limited(&mut receiver, &dm_tx, &local_data).await;
+                        break;
+                    }
                     match process_normal_message(
                         input_str,
                         &dm_tx,
@@ -346,18 +529,17 @@ async fn recv_task_normal(
 }
 
 #[inline(always)]
-fn parse_time<'a>(mut data: impl Iterator<Item = &'a str>) -> ControlFlow<bool, usize> {
+pub(crate) fn parse_time<'a>(mut data: impl Iterator<Item = &'a str>) -> ControlFlow<bool, usize> {
     let Some(time) = data.next() else {
         return ControlFlow::Break(false);
     };
     let Ok(time) = time.parse::<f32>() else {
         return ControlFlow::Break(false);
     };
-    let time = (time * 1000f32).floor() as usize;
-    if time > MAX_VIDEO_LEN {
+    let Some(time) = video_time_from_secs(time) else {
         return ControlFlow::Break(true);
-    }
-    return ControlFlow::Continue(time);
+    };
+    ControlFlow::Continue(time)
 }
 
 #[inline(always)]
@@ -371,7 +553,7 @@ fn parse_state<'a>(mut data: impl Iterator<Item = &'a str>) -> ControlFlow<bool,
     if state > STATE_MAX {
         return ControlFlow::Break(true);
     }
-    return ControlFlow::Continue(state);
+    ControlFlow::Continue(state)
 }
 
 async fn process_privileged_message(
@@ -385,6 +567,7 @@ async fn process_privileged_message(
 
     let state_data = &local_data.room_state.data;
     let broadcast_tx = &local_data.room_state.broadcast_tx;
+    metrics::packet_handled(Handler::Privileged, data_type);
 
     match data_type {
         "state" => {
@@ -404,6 +587,7 @@ async fn process_privileged_message(
                 || ((time as isize).abs_diff(read_state_data.get_time() as isize) as u128)
                     > SYNC_TIMEOUT;
             if needs_update {
+                metrics::resync(Handler::Privileged);
                 {
                     drop(read_state_data);
                     let mut state_data = state_data.write().await;
@@ -414,74 +598,49 @@ async fn process_privileged_message(
                     .arg(time.to_string())
                     .arg(video_state.to_string())
                     .into();
-                if let Err(err) = broadcast_tx.send(msg.into_server_shared_bytes()) {
+                if let Err(err) = broadcast_tx.send(msg.prepare()) {
                     return ControlFlow::Break(Some(err.to_string()));
                 }
             } else {
+                // The queue might be full, never wait on it while holding the lock.
+                drop(read_state_data);
                 let msg = StringPacket::new("state_ok");
-                if let Err(err) = dm_tx.send(msg.into()) {
+                if let Err(err) = dm_tx.send(msg.into()).await {
                     return ControlFlow::Break(Some(err.to_string()));
                 }
             }
             return ControlFlow::Continue(true);
         }
-        "seek" => {
+        "seek" | "play" | "pause" => {
             let time = match parse_time(&mut data.split("|.|")) {
                 ControlFlow::Break(con) => return ControlFlow::Continue(!con),
                 ControlFlow::Continue(time) => time,
             };
 
-            {
-                let mut state_data = state_data.write().await;
-                state_data.set_time(time);
-            }
-            println!("{}: {} at {}ms", local_data.name, data_type, time);
-
-            let msg: WebSocketMessage = StringPacket::new(data_type).arg(time.to_string()).into();
-            if let Err(err) = broadcast_tx.send(msg.into_server_shared_bytes()) {
-                return ControlFlow::Break(Some(err.to_string()));
-            }
-        }
-        "play" => {
-            let time = match parse_time(&mut data.split("|.|")) {
-                ControlFlow::Break(con) => return ControlFlow::Continue(!con),
-                ControlFlow::Continue(time) => time,
-            };
-
-            {
-                let mut state_data = state_data.write().await;
-                state_data.set_state(time, STATE_PLAY);
-            }
-
-            println!("{}: {} at {}ms", local_data.name, data_type, time);
-
-            let msg: WebSocketMessage = StringPacket::new(data_type).arg(time.to_string()).into();
-            if let Err(err) = broadcast_tx.send(msg.into_server_shared_bytes()) {
-                return ControlFlow::Break(Some(err.to_string()));
-            }
-        }
-        "pause" => {
-            let time = match parse_time(&mut data.split("|.|")) {
-                ControlFlow::Break(con) => return ControlFlow::Continue(!con),
-                ControlFlow::Continue(time) => time,
+            let room_state = &local_data.room_state;
+            let res = match data_type {
+                "seek" => room_state.seek(time).await,
+                "play" => room_state.play(time).await,
+                _ => room_state.pause(time).await,
             };
 
-            {
-                let mut state_data = state_data.write().await;
-                state_data.set_state(time, STATE_PAUSE);
-            }
-
-            println!("{}: {} at {}ms", local_data.name, data_type, time);
+            log::info!(
+                "room={} user={} peer={} {} at {}ms",
+                room_state.id,
+                local_data.id,
+                local_data.addr,
+                data_type,
+                time
+            );
 
-            let msg: WebSocketMessage = StringPacket::new(data_type).arg(time.to_string()).into();
-            if let Err(err) = broadcast_tx.send(msg.into_server_shared_bytes()) {
+            if let Err(err) = res {
                 return ControlFlow::Break(Some(err.to_string()));
             }
         }
         _ => {}
     }
 
-    return ControlFlow::Continue(false);
+    ControlFlow::Continue(false)
 }
 
 async fn process_normal_message(
@@ -493,6 +652,7 @@ async fn process_normal_message(
     let Some((data_type, data)) = check_str_packet(&input_str) else {
         return ControlFlow::Continue(false);
     };
+    metrics::packet_handled(Handler::Normal, data_type);
 
     match data_type {
         "state" => {
@@ -512,15 +672,19 @@ async fn process_normal_message(
                 || ((time as isize).abs_diff(read_state_data.get_time() as isize) as u128)
                     > SYNC_TIMEOUT;
             if needs_update {
+                metrics::resync(Handler::Normal);
                 let msg = StringPacket::new("state")
                     .arg(read_state_data.get_time().to_string())
                     .arg(read_state_data.get_state().to_string());
-                if let Err(err) = dm_tx.send(msg.into()) {
+                // The queue might be full, never wait on it while holding the lock.
+                drop(read_state_data);
+                if let Err(err) = dm_tx.send(msg.into()).await {
                     return ControlFlow::Break(Some(err.to_string()));
                 }
             } else {
+                drop(read_state_data);
                 let msg = StringPacket::new("state_ok");
-                if let Err(err) = dm_tx.send(msg.into()) {
+                if let Err(err) = dm_tx.send(msg.into()).await {
                     return ControlFlow::Break(Some(err.to_string()));
                 }
             }
@@ -529,25 +693,27 @@ async fn process_normal_message(
         "pause" | "play" | "seek" => {
             let data = state_data.read().await;
             let data_str = StringPacket::new("video_data")
-                .arg(video_data_json(data.deref(), permission.into()));
-            if let Err(err) = dm_tx.send(data_str.into()) {
+                .arg(video_data_json(data.deref(), Some(permission.into())));
+            drop(data);
+            if let Err(err) = dm_tx.send(data_str.into()).await {
                 return ControlFlow::Break(Some(err.to_string()));
             };
             return ControlFlow::Continue(true);
         }
         _ => {}
     }
-    return ControlFlow::Continue(false);
+    ControlFlow::Continue(false)
 }
 
 async fn verify_join_msg(
     msg: Message,
     ws_state: &'static WsState,
     who: &SocketAddr,
+    account: Option<&Account>,
 ) -> Result<(LocalUserState, Permission), ValidationError> {
     match msg {
         Message::Text(t) => {
-            println!(">>> {}: {}", who, &t);
+            log::trace!("peer={} received join text: {}", who, Redacted(&t));
             let Some((data_type, data)) = check_str_packet(&t) else {
                 return Err(ValidationError::InvalidPacket);
             };
@@ -560,16 +726,24 @@ async fn verify_join_msg(
                     let Some(name) = data.next() else {
                         return Err(ValidationError::InvalidPacket);
                     };
+                    // Accounts always show up with their display name.
+                    let name = match account {
+                        Some(account) => account.display_name.as_str(),
+                        None => {
+                            validation::check_user_name(name)?;
+                            name
+                        }
+                    };
 
-                    let Ok(room_id) = Id::from_str(room_id) else {
+                    let Ok(room_id) = RoomId::from_str(room_id) else {
                         return Err(ValidationError::InvalidPacket);
                     };
 
-                    println!("Trying to join room: {}", room_id);
+                    log::debug!("room={} peer={} trying to join", room_id, who);
 
                     let room = ws_state.get_room(room_id).map_err(ValidationError::from)?;
                     let room_data = room.data.read().await;
-                    return ws_state
+                    ws_state
                         .join_room(room_id)
                         .map_err(ValidationError::from)
                         .map(|(id, room_state)| {
@@ -578,12 +752,42 @@ async fn verify_join_msg(
                                     name: name.to_owned(),
                                     id,
                                     room_state,
+                                    addr: *who,
+                                    spectator: false,
+                                    account: account.map(Account::key),
                                 },
                                 room_data.get_permission(),
                             )
-                        });
+                        })
                 }
-                _ => return Err(ValidationError::InvalidPacket),
+                "spectate_room" => {
+                    let Some(room_id) = data.split("|.|").next() else {
+                        return Err(ValidationError::InvalidPacket);
+                    };
+                    let Ok(room_id) = RoomId::from_str(room_id) else {
+                        return Err(ValidationError::InvalidPacket);
+                    };
+
+                    log::debug!("room={} peer={} trying to spectate", room_id, who);
+
+                    ws_state
+                        .join_room_as_spectator(room_id)
+                        .map_err(ValidationError::from)
+                        .map(|(id, room_state)| {
+                            (
+                                LocalUserState {
+                                    name: String::from("spectator"),
+                                    id,
+                                    room_state,
+                                    addr: *who,
+                                    spectator: true,
+                                    account: account.map(Account::key),
+                                },
+                                Permission::default(),
+                            )
+                        })
+                }
+                _ => Err(ValidationError::InvalidPacket),
             }
         }
         _ => Err(ValidationError::InvalidPacket),
@@ -592,21 +796,36 @@ async fn verify_join_msg(
 
 pub async fn validate_and_handle_client(
     ws_state: &'static WsState,
+    packet_limiter: &'static RateLimiter,
     mut socket: WebSocket,
     who: SocketAddr,
     owner: Option<OwnerAuth>,
+    account: Option<Account>,
 ) {
     let (local_user, permision) = if let Some(owner_auth) = owner {
         match ws_state.join_room(owner_auth.room_id) {
             Ok((id, room_state)) => (
                 LocalUserState {
-                    name: owner_auth.username,
+                    name: account
+                        .as_ref()
+                        .map_or(owner_auth.username, |account| account.display_name.clone()),
                     id,
                     room_state,
+                    addr: who,
+                    spectator: false,
+                    account: account.as_ref().map(Account::key),
                 },
                 PERMISSION_ALL.into(),
             ),
-            Err(_) => return,
+            Err(err) => {
+                log::debug!(
+                    "room={} peer={} owner join rejected: {}",
+                    owner_auth.room_id,
+                    who,
+                    err
+                );
+                return;
+            }
         }
     } else {
         let msg = tokio::select! {
@@ -619,9 +838,10 @@ pub async fn validate_and_handle_client(
             _ = tokio::time::sleep(std::time::Duration::from_millis(CLIENT_TIMEOUT)) => return
         };
 
-        match verify_join_msg(msg, ws_state, &who).await {
+        match verify_join_msg(msg, ws_state, &who, account.as_ref()).await {
             Ok(local_user) => local_user,
             Err(err) => {
+                log::debug!("peer={} join rejected: {}", who, err);
                 let _ = socket
                     .send(Message::Close(Some(CloseFrame {
                         code: crate::sturdy_ws::CloseCode::Error,
@@ -634,5 +854,5 @@ pub async fn validate_and_handle_client(
         }
     };
 
-    user_handle(socket, who, local_user, permision, ws_state).await;
+    user_handle(socket, who, local_user, permision, ws_state, packet_limiter).await;
 }
@@ -1,9 +1,13 @@
+use std::net::SocketAddr;
+use std::str::FromStr;
 use std::sync::atomic::AtomicU32;
 use std::sync::Arc;
 
 use http::StatusCode;
 use scc::HashMap;
+use serde::Serialize;
 use tokio::sync::broadcast;
+use tokio::sync::watch;
 use tokio::sync::RwLock;
 
 use internal_server_error::InternalServerError;
@@ -11,38 +15,100 @@ use thiserror::Error;
 
 use crate::basic_auth::{Keys, OwnerAuth, CHECKED_AUTH_EXPIRATION};
 use crate::common::utils::get_elapsed_milis;
-use crate::common::{get_new_id, HashContainer, Id};
-use crate::sturdy_ws::{CloseCode, CloseFrame, WebSocketMessage};
+use crate::common::{get_new_id, HashContainer, RoomId, UserId};
+use crate::sturdy_ws::{CloseCode, WebSocketMessage};
 
-use super::user_state::UserState;
+use super::room_alias::{self, alias_key, MAX_SLUG_LEN, MIN_SLUG_LEN};
+use super::user_state::{StringPacket, UserState};
 use super::{room_state::RoomState, VideoData};
 
 pub const DEFAULT_WS: &str = "room/ws";
-const MAX_USERS: u32 = 100;
+pub const MAX_USERS: u32 = 100;
+pub const MAX_SPECTATORS: u32 = 1000;
+/// Random short codes tried before giving up, they only run out with a lot of rooms.
+const SHORT_CODE_ATTEMPTS: usize = 8;
 
 #[derive(Debug, Error, InternalServerError)]
 pub enum WebSocketStateError {
     #[error("Max user exceeded!")]
     #[code(StatusCode::BAD_REQUEST)]
     MaxUserExceeded,
+    #[error("Max spectator exceeded!")]
+    #[code(StatusCode::BAD_REQUEST)]
+    MaxSpectatorExceeded,
     #[error("The spcified room doesn't exist.")]
     #[code(StatusCode::BAD_REQUEST)]
     NoRoom,
     #[error("The spcified room is full.")]
     #[code(StatusCode::BAD_REQUEST)]
     RoomFull,
+    #[error("The spcified room can't take any more spectators.")]
+    #[code(StatusCode::BAD_REQUEST)]
+    SpectatorsFull,
     #[error("The spcified user doesn't exist.")]
     #[code(StatusCode::BAD_REQUEST)]
     NoUser,
     #[error("The spcified owner doesn't exist.")]
     #[code(StatusCode::BAD_REQUEST)]
     NoOwner,
+    #[error(
+        "The room slug must be {MIN_SLUG_LEN} to {MAX_SLUG_LEN} letters, digits or inner dashes."
+    )]
+    #[code(StatusCode::BAD_REQUEST)]
+    InvalidSlug,
+    #[error("The room slug is already taken.")]
+    #[code(StatusCode::CONFLICT)]
+    SlugTaken,
+    #[error("No short code is available right now.")]
+    #[code(StatusCode::SERVICE_UNAVAILABLE)]
+    NoShortCode,
+}
+
+/// What became of a connection told to go away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disconnect {
+    /// The close frame was queued.
+    Closed,
+    /// The queue stayed full, the connection was dropped without a close frame.
+    Dropped,
+    /// The connection was already gone.
+    Gone,
+}
+
+/// How many connections were told to go away, connections already gone aren't counted.
+#[derive(Debug, Default)]
+pub struct Disconnected {
+    pub closed: usize,
+    pub dropped: usize,
+}
+
+impl Disconnected {
+    fn add(&mut self, disconnect: Disconnect) {
+        match disconnect {
+            Disconnect::Closed => self.closed += 1,
+            Disconnect::Dropped => self.dropped += 1,
+            Disconnect::Gone => {}
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct UserInfo {
+    pub id: String,
+    pub name: String,
+    pub addr: SocketAddr,
+    pub spectator: bool,
+    pub account: Option<String>,
 }
 
 pub struct WsState {
-    pub(super) users: HashContainer<UserState>,
-    pub(super) rooms: HashContainer<Arc<RoomState>>,
-    pub(super) checked_auth_ids: HashMap<Id, (OwnerAuth, u128)>,
+    pub(super) users: HashContainer<UserId, UserState>,
+    pub(super) rooms: HashContainer<RoomId, Arc<RoomState>>,
+    /// Short codes and slugs of the rooms, keyed by [`alias_key`].
+    pub(super) aliases: HashContainer<String, RoomId>,
+    pub(super) checked_auth_ids: HashMap<UserId, (OwnerAuth, u128)>,
+    /// Turns `true` once the server is shutting down.
+    shutting_down: watch::Sender<bool>,
     pub keys: Keys,
 }
 
@@ -57,7 +123,9 @@ impl Default for WsState {
         Self {
             users: HashContainer::with_capacity(20),
             rooms: HashContainer::with_capacity(10),
+            aliases: HashContainer::with_capacity(10),
             checked_auth_ids: HashMap::with_capacity(10),
+            shutting_down: watch::channel(false).0,
             keys,
         }
     }
@@ -71,33 +139,116 @@ impl WsState {
             .await;
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_room(
         &'static self,
         data: VideoData,
         name: String,
         max_users: u32,
-    ) -> Result<(Id, String), WebSocketStateError> {
-        if max_users > MAX_USERS {
+        max_spectators: u32,
+        short_code: bool,
+        slug: Option<&str>,
+        owner_account: Option<String>,
+    ) -> Result<(Arc<RoomState>, String), WebSocketStateError> {
+        if max_users == 0 || max_users > MAX_USERS {
             return Err(WebSocketStateError::MaxUserExceeded);
         }
+        if max_spectators > MAX_SPECTATORS {
+            return Err(WebSocketStateError::MaxSpectatorExceeded);
+        }
+        let slug = slug
+            .map(|slug| room_alias::normalize_slug(slug).ok_or(WebSocketStateError::InvalidSlug))
+            .transpose()?;
+
         let room_id = get_new_id();
+        if let Some(slug) = &slug {
+            if self
+                .aliases
+                .insert_async(slug.clone(), room_id)
+                .await
+                .is_err()
+            {
+                return Err(WebSocketStateError::SlugTaken);
+            }
+        }
+        let short_code = if short_code {
+            match self.reserve_short_code(room_id).await {
+                Some(code) => Some(code),
+                None => {
+                    if let Some(slug) = &slug {
+                        self.aliases.remove_async(slug).await;
+                    }
+                    return Err(WebSocketStateError::NoShortCode);
+                }
+            }
+        } else {
+            None
+        };
+
         let (broadcast_tx, _broadcast_rx) = broadcast::channel(max_users as usize);
         let data = RwLock::new(data);
         let room = Arc::new(RoomState {
             id: room_id,
             name,
+            short_code,
+            slug,
+            owner_account,
             broadcast_tx,
             data,
             remaining_users: AtomicU32::new(max_users),
             max_users,
+            remaining_spectators: AtomicU32::new(max_spectators),
+            max_spectators,
         });
 
-        let _ = self.rooms.insert_async(room_id, room).await;
+        let _ = self.rooms.insert_async(room_id, room.clone()).await;
 
-        Ok((room_id, DEFAULT_WS.into()))
+        Ok((room, DEFAULT_WS.into()))
+    }
+
+    async fn reserve_short_code(&self, room_id: RoomId) -> Option<String> {
+        for _ in 0..SHORT_CODE_ATTEMPTS {
+            let code = room_alias::new_short_code();
+            if self
+                .aliases
+                .insert_async(alias_key(&code), room_id)
+                .await
+                .is_ok()
+            {
+                return Some(code);
+            }
+        }
+        None
     }
 
-    pub fn verify_room(&self, room_id: Id) -> Result<(Id, String, String), WebSocketStateError> {
+    /// Finds the room behind an id, a short code or a slug.
+    pub fn resolve_room(&self, room: &str) -> Result<RoomId, WebSocketStateError> {
+        if let Ok(room_id) = RoomId::from_str(room) {
+            if self.rooms.read(&room_id, |_, _| ()).is_some() {
+                return Ok(room_id);
+            }
+        }
+        self.aliases
+            .read(alias_key(room).as_str(), |_, room_id| *room_id)
+            .ok_or(WebSocketStateError::NoRoom)
+    }
+
+    /// Removes the room along with its aliases, returns whether it existed.
+    pub(super) async fn remove_room(&self, room_id: RoomId) -> bool {
+        let Some(room) = self.rooms.read(&room_id, |_, v| v.clone()) else {
+            return false;
+        };
+        if !self.rooms.remove_async(&room_id).await {
+            return false;
+        }
+        for alias in room.short_code.iter().chain(&room.slug) {
+            self.aliases.remove_async(&alias_key(alias)).await;
+        }
+        true
+    }
+
+    pub fn verify_room(&self, room: &str) -> Result<(RoomId, String, String), WebSocketStateError> {
+        let room_id = self.resolve_room(room)?;
         // TODO: Check DB and get the proper ID? also the WebSocket path can be different than usual for load balancing stuff?
         let Some((is_full, name)) = self
             .rooms
@@ -111,7 +262,27 @@ impl WsState {
         Ok((room_id, name, DEFAULT_WS.into()))
     }
 
-    pub fn join_room(&self, room_id: Id) -> Result<(Id, Arc<RoomState>), WebSocketStateError> {
+    pub fn verify_spectating_room(
+        &self,
+        room: &str,
+    ) -> Result<(RoomId, String, String), WebSocketStateError> {
+        let room_id = self.resolve_room(room)?;
+        let Some((is_full, name)) = self
+            .rooms
+            .read(&room_id, |_, v| (v.is_full_of_spectators(), v.name.clone()))
+        else {
+            return Err(WebSocketStateError::NoRoom);
+        };
+        if is_full {
+            return Err(WebSocketStateError::SpectatorsFull);
+        }
+        Ok((room_id, name, DEFAULT_WS.into()))
+    }
+
+    pub fn join_room(
+        &self,
+        room_id: RoomId,
+    ) -> Result<(UserId, Arc<RoomState>), WebSocketStateError> {
         // TODO: Seperate socket identifier and user identifier? If we ever implement DB and stuff.
         let id = get_new_id();
         let room_state = self.get_room(room_id)?;
@@ -119,24 +290,141 @@ impl WsState {
             return Err(WebSocketStateError::RoomFull);
         }
 
-        return Ok((id, room_state));
+        Ok((id, room_state))
     }
 
-    pub fn kick_user(&self, id: Id) -> Result<(), WebSocketStateError> {
-        if let None = self.users.read(&id, |_, v| {
-            v.tx.send(WebSocketMessage::Close(Some(CloseFrame {
-                code: CloseCode::Protocol,
-                reason: std::borrow::Cow::Borrowed("Test"),
-            })))
-        }) {
-            return Err(WebSocketStateError::NoUser);
-        };
+    pub fn join_room_as_spectator(
+        &self,
+        room_id: RoomId,
+    ) -> Result<(UserId, Arc<RoomState>), WebSocketStateError> {
+        let id = get_new_id();
+        let room_state = self.get_room(room_id)?;
+        if !room_state.spectator_join() {
+            return Err(WebSocketStateError::SpectatorsFull);
+        }
+
+        Ok((id, room_state))
+    }
+
+    pub async fn kick_user(&self, id: UserId) -> Result<Disconnect, WebSocketStateError> {
+        let user = self
+            .users
+            .read(&id, |_, v| v.clone())
+            .ok_or(WebSocketStateError::NoUser)?;
+        match user.close(CloseCode::Policy, "Kicked").await {
+            Disconnect::Gone => Err(WebSocketStateError::NoUser),
+            disconnect => Ok(disconnect),
+        }
+    }
+
+    /// Disconnects every connection of the account.
+    pub async fn kick_account(&self, account: &str) -> Disconnected {
+        self.close_users(
+            |v| v.account.as_deref() == Some(account),
+            CloseCode::Policy,
+            "Banned",
+        )
+        .await
+    }
+
+    /// Disconnects everyone in the room and removes it right away.
+    pub async fn close_room(&self, room_id: RoomId) -> Result<Disconnected, WebSocketStateError> {
+        if !self.remove_room(room_id).await {
+            return Err(WebSocketStateError::NoRoom);
+        }
+        Ok(self
+            .close_users(|v| v.room_id == room_id, CloseCode::Away, "Room closed")
+            .await)
+    }
+
+    /// Sends a close frame to every connected user.
+    pub async fn disconnect_all(&self, code: CloseCode, reason: &'static str) -> Disconnected {
+        self.close_users(|_| true, code, reason).await
+    }
+
+    /// Closes the connections of the users matching `filter` all at once, so a stuck one
+    /// doesn't hold up the others.
+    async fn close_users<F: FnMut(&UserState) -> bool>(
+        &self,
+        mut filter: F,
+        code: CloseCode,
+        reason: &'static str,
+    ) -> Disconnected {
+        let mut users = Vec::new();
+        self.users.scan(|_, v| {
+            if filter(v) {
+                users.push(v.clone());
+            }
+        });
+        let closes = users.iter().map(|user| user.close(code, reason));
+        let mut disconnected = Disconnected::default();
+        for disconnect in futures_util::future::join_all(closes).await {
+            disconnected.add(disconnect);
+        }
+        disconnected
+    }
+
+    /// Marks the server as shutting down, ending the event streams waiting on
+    /// [`WsState::shutdown_started`].
+    pub fn start_shutdown(&self) {
+        self.shutting_down.send_replace(true);
+    }
+
+    /// Resolves once [`WsState::start_shutdown`] was called.
+    pub fn shutdown_started(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut shutting_down = self.shutting_down.subscribe();
+        async move {
+            let _ = shutting_down.wait_for(|shutting_down| *shutting_down).await;
+        }
+    }
+
+    #[inline]
+    pub fn get_connected_count(&self) -> usize {
+        self.users.len()
+    }
+
+    #[inline]
+    pub fn get_room_count(&self) -> usize {
+        self.rooms.len()
+    }
+
+    /// Broadcasts an `announcement` packet to every room, returns how many rooms got it.
+    pub fn announce(&self, message: &str) -> usize {
+        let msg: WebSocketMessage = StringPacket::new("announcement").arg(message).into();
+        let bytes = msg.prepare();
+        let mut count = 0;
+        self.rooms.scan(|_, v| {
+            if v.broadcast_tx.send(bytes.clone()).is_ok() {
+                count += 1;
+            }
+        });
+        count
+    }
 
-        Ok(())
+    pub fn get_rooms(&self) -> Vec<Arc<RoomState>> {
+        let mut rooms = Vec::with_capacity(self.rooms.len());
+        self.rooms.scan(|_, v| rooms.push(v.clone()));
+        rooms
+    }
+
+    pub fn get_room_users(&self, room_id: RoomId) -> Vec<UserInfo> {
+        let mut users = Vec::new();
+        self.users.scan(|_, v| {
+            if v.room_id == room_id {
+                users.push(UserInfo {
+                    id: v.id.to_string(),
+                    name: v.name.clone(),
+                    addr: v.addr,
+                    spectator: v.spectator,
+                    account: v.account.clone(),
+                });
+            }
+        });
+        users
     }
 
     #[inline]
-    pub fn get_room(&self, room_id: Id) -> Result<Arc<RoomState>, WebSocketStateError> {
+    pub fn get_room(&self, room_id: RoomId) -> Result<Arc<RoomState>, WebSocketStateError> {
         let Some(data) = self.rooms.read(&room_id, |_, v| v.clone()) else {
             return Err(WebSocketStateError::NoRoom);
         };
@@ -145,7 +433,7 @@ impl WsState {
 
     pub async fn remove_checked_auth<F: FnOnce(&mut (OwnerAuth, u128)) -> bool>(
         &self,
-        id: Id,
+        id: UserId,
         condition: F,
     ) -> Result<OwnerAuth, WebSocketStateError> {
         let Some((_, (owner_auth, _))) =
@@ -157,7 +445,7 @@ impl WsState {
         Ok(owner_auth)
     }
 
-    pub async fn add_checked_auth(&self, owner_auth: OwnerAuth) -> Id {
+    pub async fn add_checked_auth(&self, owner_auth: OwnerAuth) -> UserId {
         let id = get_new_id();
         let _ = self
             .checked_auth_ids
@@ -0,0 +1,228 @@
+mod common;
+
+use std::time::Duration;
+
+use common::TestServer;
+use hyper::{Method, StatusCode};
+use serde_json::{json, Value};
+use sturdy_spoon::sturdy_ws::{CloseCode, Message};
+
+const ADMIN_TOKEN: &str = "admin-secret";
+
+/// The only test of the file, the variables are read when the server starts and are the same for
+/// the whole process.
+#[tokio::test(start_paused = true)]
+async fn accounts_follow_the_person() {
+    let db =
+        std::env::temp_dir().join(format!("sturdy-spoon-accounts-{}.redb", std::process::id()));
+    let _ = std::fs::remove_file(&db);
+    std::env::set_var("ACCOUNTS_DB", &db);
+    std::env::set_var("ADMIN_TOKEN", ADMIN_TOKEN);
+    let server = TestServer::start().await;
+
+    // Registering logs the browser in.
+    let mut alice = server.browser();
+    let (status, body) = alice
+        .request(
+            Method::POST,
+            "/account/register",
+            Some(json!({"username": "Alice", "password": "hunter22", "display_name": "Alice A."})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(alice.cookie("session").is_some());
+    let (status, body) = alice.request(Method::GET, "/account/me", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let me: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(me, json!({"username": "Alice", "display_name": "Alice A."}));
+
+    let (status, body) = server
+        .browser()
+        .request(
+            Method::POST,
+            "/account/register",
+            Some(json!({"username": "a b", "password": "short"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let errors: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(errors["errors"].as_array().unwrap().len(), 2);
+    let (status, _) = server
+        .browser()
+        .request(
+            Method::POST,
+            "/account/register",
+            Some(json!({"username": "alice", "password": "password"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let mut bob = server.browser();
+    let (status, _) = bob
+        .request(
+            Method::POST,
+            "/account/register",
+            Some(json!({"username": "bob", "password": "password"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = bob
+        .request(
+            Method::POST,
+            "/account/me",
+            Some(json!({"display_name": "Bobby"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Logged in users show up with their display name, whatever they type.
+    let room_id = alice.create_room(2, false).await;
+    assert_eq!(alice.join(&room_id).await, Ok(true));
+    let mut owner = alice.connect().await;
+    assert_eq!(owner.expect_video_data().await["permission"], 3);
+    let mut guest = bob.connect().await;
+    guest.send("join_room", &[&room_id, "guest"]).await;
+    guest.expect_video_data().await;
+    let (packet_type, args) = owner.recv_packet().await;
+    assert_eq!(
+        (packet_type.as_str(), args[0].as_str()),
+        ("joined", "Bobby")
+    );
+    owner.close().await;
+
+    // The room belongs to the account, not to the browser which created it.
+    let mut laptop = server.browser();
+    assert_eq!(laptop.join(&room_id).await, Ok(false));
+    let (status, _) = laptop
+        .request(
+            Method::POST,
+            "/account/login",
+            Some(json!({"username": "alice", "password": "wrong password"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = laptop
+        .request(
+            Method::POST,
+            "/account/login",
+            Some(json!({"username": "nobody", "password": "hunter22"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = laptop
+        .request(
+            Method::POST,
+            "/account/login",
+            Some(json!({"username": "ALICE", "password": "hunter22"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(laptop.join(&room_id).await, Ok(true));
+    let mut owner = laptop.connect().await;
+    assert_eq!(owner.expect_video_data().await["permission"], 3);
+    owner.close().await;
+
+    // So do the rooms it created or joined, they're written in the background.
+    tokio::time::sleep(Duration::from_millis(1)).await;
+    let (status, body) = laptop.request(Method::GET, "/account/history", None).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let history: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(history.as_array().unwrap().len(), 1, "{}", history);
+    assert_eq!(history[0]["room_id"], room_id.as_str());
+    assert_eq!(history[0]["owner"], true);
+    assert_eq!(bob.join(&room_id).await, Ok(false));
+    tokio::time::sleep(Duration::from_millis(1)).await;
+    let (_, body) = bob.request(Method::GET, "/account/history", None).await;
+    let history: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(history[0]["room_id"], room_id.as_str());
+    assert_eq!(history[0]["owner"], false);
+    let (status, _) = laptop.request(Method::POST, "/account/logout", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = laptop.request(Method::GET, "/account/me", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // Logging out ends the sessions of the other browsers too, copied cookies included.
+    let (status, _) = alice.request(Method::GET, "/account/me", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // The metrics are guarded by the admin token as well.
+    let mut admin = server.browser();
+    admin.set_bearer(Some(ADMIN_TOKEN));
+    let (status, _) = server
+        .browser()
+        .request(Method::GET, "/metrics", None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = admin.request(Method::GET, "/metrics", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("sturdy_rooms 1"), "{}", body);
+
+    // Event streams follow the state of the room, announcements aren't part of it.
+    let mut events = server
+        .browser()
+        .events(&format!("/room/{}/events", room_id))
+        .await
+        .unwrap();
+    let (event, _) = events.next_event().await.unwrap();
+    assert_eq!(event, "video_data");
+    let (status, _) = admin
+        .request(
+            Method::POST,
+            "/admin/announce",
+            Some(json!({"message": "maintenance soon"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let mut viewer = server.browser().connect().await;
+    viewer.send("join_room", &[&room_id, "viewer"]).await;
+    viewer.expect_video_data().await;
+    let (event, args) = events.next_event().await.unwrap();
+    assert_eq!((event.as_str(), &args[0]), ("joined", &json!("viewer")));
+    viewer.close().await;
+
+    // A ban closes the connections of the account and keeps it out.
+    let (status, body) = admin
+        .request(Method::POST, "/admin/accounts/Bob/ban", None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let ban: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        ban,
+        json!({"username": "bob", "banned": true, "kicked": 1, "dropped": 0})
+    );
+    let close = loop {
+        match guest.recv().await {
+            Message::Close(frame) => break frame.unwrap(),
+            Message::Text(_) => continue,
+            msg => panic!("expected a close, got {:?}", msg),
+        }
+    };
+    assert_eq!(close.code, CloseCode::Policy);
+    guest.close().await;
+    let (status, _) = bob.request(Method::GET, "/account/me", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = bob.create_room_with(json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = server
+        .browser()
+        .request(
+            Method::POST,
+            "/account/login",
+            Some(json!({"username": "bob", "password": "password"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = admin
+        .request(Method::DELETE, "/admin/accounts/bob/ban", None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = bob.request(Method::GET, "/account/me", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = admin
+        .request(Method::POST, "/admin/accounts/nobody/ban", None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    server.shutdown().await;
+    let _ = std::fs::remove_file(&db);
+}
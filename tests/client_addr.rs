@@ -0,0 +1,57 @@
+mod common;
+
+use common::TestServer;
+use hyper::StatusCode;
+use serde_json::json;
+
+const CLIENT: &str = "203.0.113.7";
+const OTHER_CLIENT: &str = "203.0.113.8";
+
+/// The only test of the file, the variable is read when the server starts and is the same for
+/// the whole process.
+#[tokio::test(start_paused = true)]
+async fn clients_are_told_apart_behind_a_trusted_proxy() {
+    std::env::set_var("TRUSTED_PROXIES", "127.0.0.1/32, ::1");
+    let server = TestServer::start().await;
+
+    // Owners are bound to the address of the client, not to the one of the proxy.
+    let mut owner = server.browser();
+    owner.set_forwarded_for(Some(CLIENT));
+    let room_id = owner.create_room(2, false).await;
+    assert_eq!(owner.join(&room_id).await, Ok(true));
+    let mut client = owner.connect().await;
+    assert_eq!(client.expect_video_data().await["permission"], 3);
+    client.close().await;
+
+    // Each client has its own quota, whatever it claims before our proxy is ignored.
+    for _ in 0..9 {
+        let mut browser = server.browser();
+        browser.set_forwarded_for(Some(CLIENT));
+        browser.create_room(2, false).await;
+    }
+    let mut spoofer = server.browser();
+    spoofer.set_forwarded_for(Some(&format!("{}, {}", OTHER_CLIENT, CLIENT)));
+    let (status, _) = spoofer.create_room_with(json!({})).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let mut other = server.browser();
+    other.set_forwarded_for(Some(OTHER_CLIENT));
+    other.create_room(2, false).await;
+
+    // Owners are bound to their browser, changing networks doesn't lose them the room.
+    owner.set_forwarded_for(Some(OTHER_CLIENT));
+    assert_eq!(owner.join(&room_id).await, Ok(true));
+
+    // Pages are served over the scheme the proxy tells, the other one is another origin.
+    let https_origin = server.origin().replace("http://", "https://");
+    let mut browser = server.browser();
+    browser.set_forwarded_for(Some(OTHER_CLIENT));
+    browser.set_forwarded_proto(Some("https"));
+    browser.set_origin(Some(&server.origin()));
+    let (status, _) = browser.create_room_with(json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    browser.set_origin(Some(&https_origin));
+    let (status, body) = browser.create_room_with(json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    server.shutdown().await;
+}
@@ -0,0 +1,116 @@
+//! In memory stream and frame helpers to drive the `sturdy_tungstenite` protocol without a
+//! network.
+
+use std::collections::VecDeque;
+use std::io::{Cursor, Read, Write};
+
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::protocol::frame::{
+    coding::OpCode, Frame, FrameHeader,
+};
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::protocol::{Role, WebSocketConfig};
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::WebSocket;
+
+/// Serves its input in the given chunks, one per read, then reports the end of the stream.
+#[derive(Debug, Default)]
+pub struct MockStream {
+    input: VecDeque<Vec<u8>>,
+    pub output: Vec<u8>,
+}
+
+impl MockStream {
+    pub fn new(input: Vec<u8>) -> Self {
+        Self::chunked(vec![input])
+    }
+
+    pub fn chunked(chunks: Vec<Vec<u8>>) -> Self {
+        Self {
+            input: chunks
+                .into_iter()
+                .filter(|chunk| !chunk.is_empty())
+                .collect(),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(chunk) = self.input.front_mut() else {
+            return Ok(0);
+        };
+        let len = chunk.len().min(buf.len());
+        buf[..len].copy_from_slice(&chunk[..len]);
+        chunk.drain(..len);
+        if chunk.is_empty() {
+            self.input.pop_front();
+        }
+        Ok(len)
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A frame as a client sends it, masked.
+pub fn client_frame(opcode: OpCode, is_final: bool, payload: impl Into<Vec<u8>>) -> Frame {
+    let header = FrameHeader {
+        is_final,
+        opcode,
+        mask: Some(rand::random()),
+        ..FrameHeader::default()
+    };
+    Frame::from_payload(header, payload.into())
+}
+
+pub fn encode(frames: impl IntoIterator<Item = Frame>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for frame in frames {
+        frame.format(&mut bytes).unwrap();
+    }
+    bytes
+}
+
+pub fn server(frames: impl IntoIterator<Item = Frame>) -> WebSocket<MockStream> {
+    server_with_config(frames, WebSocketConfig::default())
+}
+
+pub fn server_with_config(
+    frames: impl IntoIterator<Item = Frame>,
+    config: WebSocketConfig,
+) -> WebSocket<MockStream> {
+    WebSocket::from_raw_socket(MockStream::new(encode(frames)), Role::Server, Some(config))
+}
+
+/// Decodes everything written to the stream, unmasking the payloads.
+pub fn decode(mut bytes: &[u8]) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    while !bytes.is_empty() {
+        let mut cursor = Cursor::new(bytes);
+        let (mut header, length) = FrameHeader::parse(&mut cursor)
+            .unwrap()
+            .expect("truncated frame header");
+        let start = cursor.position() as usize;
+        let end = start + length as usize;
+        let mut payload = bytes[start..end].to_vec();
+        if let Some(mask) = header.mask.take() {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+        frames.push(Frame::from_payload(header, payload));
+        bytes = &bytes[end..];
+    }
+    frames
+}
+
+pub fn written(ws: &WebSocket<MockStream>) -> Vec<Frame> {
+    decode(&ws.get_ref().output)
+}
@@ -0,0 +1,245 @@
+//! A local OpenID Connect issuer: discovery document, keys and token endpoint, with the user
+//! side of the authorization endpoint played by the tests.
+
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router, Server};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine as _;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+
+const KEY_ID: &str = "mock-key";
+
+struct Grant {
+    challenge: String,
+    nonce: String,
+    redirect_uri: String,
+    subject: String,
+    name: String,
+}
+
+struct Issuer {
+    url: String,
+    client_id: String,
+    client_secret: String,
+    key: Vec<u8>,
+    public_key: Vec<u8>,
+    /// Signs the next tokens with a key missing from the published ones.
+    forge: Mutex<bool>,
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+pub struct MockIssuer {
+    issuer: Arc<Issuer>,
+    handle: JoinHandle<()>,
+}
+
+fn new_key() -> Vec<u8> {
+    EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+        .unwrap()
+        .as_ref()
+        .to_vec()
+}
+
+impl MockIssuer {
+    pub async fn start(client_id: &str, client_secret: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let key = new_key();
+        let public_key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &key)
+            .unwrap()
+            .public_key()
+            .as_ref()
+            .to_vec();
+        let issuer = Arc::new(Issuer {
+            url,
+            client_id: client_id.to_owned(),
+            client_secret: client_secret.to_owned(),
+            key,
+            public_key,
+            forge: Mutex::new(false),
+            grants: Mutex::new(HashMap::new()),
+        });
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(issuer.clone());
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        let handle = tokio::spawn(async move {
+            server.await.unwrap();
+        });
+        Self { issuer, handle }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.issuer.url
+    }
+
+    pub fn forge_tokens(&self, forge: bool) {
+        *self.issuer.forge.lock().unwrap() = forge;
+    }
+
+    /// Signs `subject` in at the authorization endpoint the server sent the browser to, returns
+    /// the code and the state the issuer sends the browser back with.
+    pub fn authorize(
+        &self,
+        authorization_url: &str,
+        subject: &str,
+        name: &str,
+    ) -> (String, String) {
+        let (endpoint, query) = authorization_url.split_once('?').unwrap();
+        assert_eq!(endpoint, format!("{}/authorize", self.issuer.url));
+        let params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        assert_eq!(params["response_type"], "code");
+        assert_eq!(params["client_id"], self.issuer.client_id);
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert!(params["scope"].split(' ').any(|scope| scope == "openid"));
+
+        let code = format!("code-{}", self.issuer.grants.lock().unwrap().len());
+        self.issuer.grants.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                challenge: params["code_challenge"].clone(),
+                nonce: params["nonce"].clone(),
+                redirect_uri: params["redirect_uri"].clone(),
+                subject: subject.to_owned(),
+                name: name.to_owned(),
+            },
+        );
+        (code, params["state"].clone())
+    }
+
+    pub async fn shutdown(self) {
+        self.handle.abort();
+        let _ = self.handle.await;
+    }
+}
+
+async fn discovery(State(issuer): State<Arc<Issuer>>) -> Json<Value> {
+    Json(json!({
+        "issuer": issuer.url,
+        "authorization_endpoint": format!("{}/authorize", issuer.url),
+        "token_endpoint": format!("{}/token", issuer.url),
+        "jwks_uri": format!("{}/jwks", issuer.url),
+        "response_types_supported": ["code"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+async fn jwks(State(issuer): State<Arc<Issuer>>) -> Json<Value> {
+    let (x, y) = issuer.public_key[1..].split_at(32);
+    Json(json!({
+        "keys": [{
+            "kty": "EC",
+            "crv": "P-256",
+            "use": "sig",
+            "alg": "ES256",
+            "kid": KEY_ID,
+            "x": URL_SAFE_NO_PAD.encode(x),
+            "y": URL_SAFE_NO_PAD.encode(y),
+        }],
+    }))
+}
+
+fn invalid_grant() -> axum::response::Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({"error": "invalid_grant"})),
+    )
+        .into_response()
+}
+
+async fn token(
+    State(issuer): State<Arc<Issuer>>,
+    headers: HeaderMap,
+    body: String,
+) -> axum::response::Response {
+    // client_secret_basic, both parts are form encoded before going into the header.
+    let encode =
+        |value: &str| form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>();
+    let credentials = format!(
+        "{}:{}",
+        encode(&issuer.client_id),
+        encode(&issuer.client_secret)
+    );
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if authorization != Some(format!("Basic {}", STANDARD.encode(credentials)).as_str()) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "invalid_client"})),
+        )
+            .into_response();
+    }
+
+    let form: HashMap<String, String> = form_urlencoded::parse(body.as_bytes())
+        .into_owned()
+        .collect();
+    if form.get("grant_type").map(String::as_str) != Some("authorization_code") {
+        return invalid_grant();
+    }
+    let Some(grant) = form
+        .get("code")
+        .and_then(|code| issuer.grants.lock().unwrap().remove(code))
+    else {
+        return invalid_grant();
+    };
+    let verifier = form.get("code_verifier").cloned().unwrap_or_default();
+    let challenge = URL_SAFE_NO_PAD.encode(ring::digest::digest(
+        &ring::digest::SHA256,
+        verifier.as_bytes(),
+    ));
+    if challenge != grant.challenge
+        || form.get("redirect_uri") != Some(&grant.redirect_uri)
+        || form.get("client_id") != Some(&issuer.client_id)
+    {
+        return invalid_grant();
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let claims = json!({
+        "iss": issuer.url,
+        "aud": issuer.client_id,
+        "sub": grant.subject,
+        "name": grant.name,
+        "email": format!("{}@example.com", grant.subject),
+        "nonce": grant.nonce,
+        "iat": now,
+        "exp": now + 300,
+    });
+    let key = if *issuer.forge.lock().unwrap() {
+        new_key()
+    } else {
+        issuer.key.clone()
+    };
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some(KEY_ID.into());
+    let id_token = jsonwebtoken::encode(&header, &claims, &EncodingKey::from_ec_der(&key)).unwrap();
+    Json(json!({
+        "access_token": "mock-access-token",
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": id_token,
+    }))
+    .into_response()
+}
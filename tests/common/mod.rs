@@ -0,0 +1,410 @@
+//! Harness booting the whole server on an ephemeral port, with simulated browsers talking to it
+//! over HTTP and WebSockets.
+#![allow(dead_code)]
+
+pub mod mock;
+pub mod mock_issuer;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::TcpListener;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use hyper::body::HttpBody;
+use hyper::{body, client::HttpConnector, Body, Client, HeaderMap, Method, Request, StatusCode};
+use serde_json::Value;
+use sturdy_spoon::server_state::ServerState;
+use sturdy_spoon::sturdy_ws::{
+    client::WebSocketConnector, sturdy_tungstenite::error::Error as WsError,
+    ws_stream::WebSocketStream, CloseFrame, Message,
+};
+use tokio::{net::TcpStream, sync::oneshot, task::JoinHandle};
+
+pub const USER_AGENT: &str = "sturdy-spoon-tests";
+
+/// Nothing should take this long on loopback, only reached when a message never comes.
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Waits on the network with tokio's paused clock frozen.
+///
+/// Loopback isn't instant, without this the paused clock jumps straight to the next timer
+/// whenever the runtime is idle, firing timeouts before the bytes had a chance to arrive. The
+/// timeout is in real time for the same reason.
+pub async fn network<F: Future>(fut: F) -> F::Output {
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+    // The clock doesn't auto-advance while a blocking task is running.
+    let mut freeze = tokio::task::spawn_blocking(move || done_rx.recv_timeout(NETWORK_TIMEOUT));
+    tokio::select! {
+        output = fut => {
+            drop(done_tx);
+            let _ = freeze.await;
+            output
+        }
+        _ = &mut freeze => panic!("timed out waiting on the network"),
+    }
+}
+
+pub struct TestServer {
+    addr: std::net::SocketAddr,
+    http: Client<HttpConnector>,
+    shutdown: Option<oneshot::Sender<()>>,
+    handle: JoinHandle<()>,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let handle = tokio::spawn(sturdy_spoon::run_server(
+            ServerState::new(),
+            listener,
+            async move {
+                let _ = shutdown_rx.await;
+            },
+        ));
+        Self {
+            addr,
+            http: Client::new(),
+            shutdown: Some(shutdown_tx),
+            handle,
+        }
+    }
+
+    /// Starts the graceful shutdown and waits for the server to be done.
+    pub async fn shutdown(mut self) {
+        let _ = self.shutdown.take().unwrap().send(());
+        self.handle.await.unwrap();
+    }
+
+    /// The origin of the pages the server serves.
+    pub fn origin(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn browser(&self) -> Browser<'_> {
+        Browser {
+            server: self,
+            cookies: HashMap::new(),
+            forwarded_for: None,
+            forwarded_proto: None,
+            origin: None,
+            bearer: None,
+        }
+    }
+}
+
+/// Keeps cookies between requests, like a browser would.
+pub struct Browser<'a> {
+    server: &'a TestServer,
+    cookies: HashMap<String, String>,
+    forwarded_for: Option<String>,
+    forwarded_proto: Option<String>,
+    origin: Option<String>,
+    bearer: Option<String>,
+}
+
+impl Browser<'_> {
+    /// Sends `X-Forwarded-For` with every request, as if the browser was behind a proxy.
+    pub fn set_forwarded_for(&mut self, forwarded_for: Option<&str>) {
+        self.forwarded_for = forwarded_for.map(str::to_owned);
+    }
+
+    /// Sends `X-Forwarded-Proto` with every request, as if a proxy terminated TLS for the browser.
+    pub fn set_forwarded_proto(&mut self, forwarded_proto: Option<&str>) {
+        self.forwarded_proto = forwarded_proto.map(str::to_owned);
+    }
+
+    /// Sends `Origin` with every request, as if a page of that origin made them.
+    pub fn set_origin(&mut self, origin: Option<&str>) {
+        self.origin = origin.map(str::to_owned);
+    }
+
+    /// Sends `Authorization: Bearer` with every request.
+    pub fn set_bearer(&mut self, token: Option<&str>) {
+        self.bearer = token.map(str::to_owned);
+    }
+
+    pub async fn request(
+        &mut self,
+        method: Method,
+        path: &str,
+        json: Option<Value>,
+    ) -> (StatusCode, String) {
+        let (status, _, body) = self.request_with_headers(method, path, json).await;
+        (status, body)
+    }
+
+    /// Follows nothing, returns the status and where the browser is being redirected.
+    pub async fn get_redirect(&mut self, path: &str) -> (StatusCode, Option<String>) {
+        let (status, headers, _) = self.request_with_headers(Method::GET, path, None).await;
+        let location = headers
+            .get(hyper::header::LOCATION)
+            .map(|location| location.to_str().unwrap().to_owned());
+        (status, location)
+    }
+
+    pub async fn request_with_headers(
+        &mut self,
+        method: Method,
+        path: &str,
+        json: Option<Value>,
+    ) -> (StatusCode, HeaderMap, String) {
+        let request = self.build_request(method, path, json);
+        let response = network(self.server.http.request(request)).await.unwrap();
+        for cookie in response.headers().get_all(hyper::header::SET_COOKIE) {
+            let cookie = cookie.to_str().unwrap();
+            let pair = cookie.split(';').next().unwrap();
+            let (name, value) = pair.split_once('=').unwrap();
+            let removed = cookie.contains("Max-Age=0") || value.is_empty();
+            if removed {
+                self.cookies.remove(name);
+            } else {
+                self.cookies.insert(name.to_owned(), value.to_owned());
+            }
+        }
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = network(body::to_bytes(response.into_body())).await.unwrap();
+        (status, headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn build_request(&self, method: Method, path: &str, json: Option<Value>) -> Request<Body> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("http://{}{}", self.server.addr, path))
+            .header(hyper::header::USER_AGENT, USER_AGENT)
+            .header(hyper::header::CONTENT_TYPE, "application/json");
+        if let Some(cookie) = self.cookie_header() {
+            request = request.header(hyper::header::COOKIE, cookie);
+        }
+        if let Some(forwarded_for) = &self.forwarded_for {
+            request = request.header("x-forwarded-for", forwarded_for);
+        }
+        if let Some(forwarded_proto) = &self.forwarded_proto {
+            request = request.header("x-forwarded-proto", forwarded_proto);
+        }
+        if let Some(origin) = &self.origin {
+            request = request.header(hyper::header::ORIGIN, origin);
+        }
+        if let Some(token) = &self.bearer {
+            request = request.header(hyper::header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let body = json.map_or_else(Body::empty, |json| Body::from(json.to_string()));
+        request.body(body).unwrap()
+    }
+
+    /// Opens the Server-Sent Events stream at `path`.
+    pub async fn events(&self, path: &str) -> Result<EventStream, StatusCode> {
+        let request = self.build_request(Method::GET, path, None);
+        let response = network(self.server.http.request(request)).await.unwrap();
+        if response.status() != StatusCode::OK {
+            return Err(response.status());
+        }
+        Ok(EventStream {
+            body: response.into_body(),
+            buffer: String::new(),
+        })
+    }
+
+    /// Creates a room and returns its id, the browser becomes its owner.
+    pub async fn create_room(&mut self, max_users: i32, global_control: bool) -> String {
+        let (status, body) = self
+            .create_room_with(serde_json::json!({
+                "max_users": max_users,
+                "global_control": global_control,
+            }))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let room: Value = serde_json::from_str(&body).unwrap();
+        room["id"].as_str().unwrap().to_owned()
+    }
+
+    /// Sends a room creation request, with `fields` replacing the defaults of the payload.
+    pub async fn create_room_with(&mut self, fields: Value) -> (StatusCode, String) {
+        let mut payload = serde_json::json!({
+            "name": "room",
+            "creator_name": "owner",
+            "video_url": "https://example.com/video.mp4",
+            "cc_url": "",
+            "max_users": 2,
+            "global_control": false,
+            "player_index": 0,
+        });
+        for (key, value) in fields.as_object().unwrap() {
+            payload[key] = value.clone();
+        }
+        self.request(Method::POST, "/room/create", Some(payload))
+            .await
+    }
+
+    /// Opens the room page, returns whether the browser owns the room.
+    pub async fn join(&mut self, room_id: &str) -> Result<bool, StatusCode> {
+        let (status, body) = self
+            .request(Method::GET, &format!("/room/{}", room_id), None)
+            .await;
+        if status != StatusCode::OK {
+            return Err(status);
+        }
+        Ok(body.contains("let autoConnect = true;"))
+    }
+
+    /// Opens the room WebSocket with the cookies of the browser.
+    pub async fn connect(&mut self) -> TestClient {
+        self.try_connect().await.unwrap()
+    }
+
+    /// Opens the room WebSocket, returns the status of the refused handshakes.
+    pub async fn try_connect(&mut self) -> Result<TestClient, StatusCode> {
+        let mut connector = WebSocketConnector::new(&format!("ws://{}/room/ws", self.server.addr))
+            .unwrap()
+            .header(hyper::header::USER_AGENT, USER_AGENT.parse().unwrap());
+        if let Some(cookie) = self.cookie_header() {
+            connector = connector.header(hyper::header::COOKIE, cookie.parse().unwrap());
+        }
+        if let Some(forwarded_for) = &self.forwarded_for {
+            connector = connector.header(
+                hyper::header::HeaderName::from_static("x-forwarded-for"),
+                forwarded_for.parse().unwrap(),
+            );
+        }
+        if let Some(forwarded_proto) = &self.forwarded_proto {
+            connector = connector.header(
+                hyper::header::HeaderName::from_static("x-forwarded-proto"),
+                forwarded_proto.parse().unwrap(),
+            );
+        }
+        if let Some(origin) = &self.origin {
+            connector = connector.header(hyper::header::ORIGIN, origin.parse().unwrap());
+        }
+        let socket = match network(connector.connect()).await {
+            Ok((socket, _)) => socket,
+            Err(WsError::Http(status)) => return Err(status),
+            Err(err) => panic!("failed to connect: {}", err),
+        };
+        // The checked cookie can only be used once.
+        self.cookies.remove("checked_auth");
+        Ok(TestClient { socket })
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies.get(name).map(String::as_str)
+    }
+
+    pub fn set_cookie(&mut self, name: &str, value: &str) {
+        self.cookies.insert(name.to_owned(), value.to_owned());
+    }
+
+    pub fn remove_cookie(&mut self, name: &str) {
+        self.cookies.remove(name);
+    }
+
+    fn cookie_header(&self) -> Option<String> {
+        if self.cookies.is_empty() {
+            return None;
+        }
+        let cookies: Vec<_> = self
+            .cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        Some(cookies.join("; "))
+    }
+}
+
+/// A `text/event-stream` response, read one event at a time.
+pub struct EventStream {
+    body: Body,
+    buffer: String,
+}
+
+impl EventStream {
+    /// The name and the JSON data of the next event, `None` once the server ended the stream.
+    pub async fn next_event(&mut self) -> Option<(String, Value)> {
+        loop {
+            while let Some(end) = self.buffer.find("\n\n") {
+                let event: String = self.buffer.drain(..end + 2).collect();
+                let mut name = None;
+                let mut data = None;
+                for line in event.lines() {
+                    if let Some(value) = line.strip_prefix("event:") {
+                        name = Some(value.trim().to_owned());
+                    } else if let Some(value) = line.strip_prefix("data:") {
+                        data = Some(serde_json::from_str(value.trim()).unwrap());
+                    }
+                }
+                // Keep-alive comments have neither.
+                if let (Some(name), Some(data)) = (name, data) {
+                    return Some((name, data));
+                }
+            }
+            let chunk = network(self.body.data()).await?.unwrap();
+            self.buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    }
+}
+
+pub struct TestClient {
+    socket: WebSocketStream<TcpStream>,
+}
+
+impl TestClient {
+    pub async fn send(&mut self, packet_type: &str, args: &[&str]) {
+        let packet = format!("||-=-||{}-=-{}", packet_type, args.join("|.|"));
+        self.socket.send(Message::Text(packet)).await.unwrap();
+    }
+
+    pub async fn recv(&mut self) -> Message {
+        network(self.socket.next())
+            .await
+            .expect("the connection is closed")
+            .unwrap()
+    }
+
+    /// Receives the next packet, split into its type and arguments.
+    pub async fn recv_packet(&mut self) -> (String, Vec<String>) {
+        let msg = self.recv().await;
+        let Message::Text(text) = msg else {
+            panic!("expected a text packet, got {:?}", msg);
+        };
+        let (packet_type, args) = text
+            .strip_prefix("||-=-||")
+            .and_then(|packet| packet.split_once("-=-"))
+            .unwrap_or_else(|| panic!("not a packet: {}", text));
+        let args = match args {
+            "" => Vec::new(),
+            args => args.split("|.|").map(str::to_owned).collect(),
+        };
+        (packet_type.to_owned(), args)
+    }
+
+    pub async fn expect(&mut self, packet_type: &str, args: &[&str]) {
+        let (received_type, received_args) = self.recv_packet().await;
+        let received_args: Vec<&str> = received_args.iter().map(String::as_str).collect();
+        assert_eq!(
+            (received_type.as_str(), received_args.as_slice()),
+            (packet_type, args),
+        );
+    }
+
+    pub async fn expect_video_data(&mut self) -> Value {
+        let (packet_type, args) = self.recv_packet().await;
+        assert_eq!(packet_type, "video_data");
+        serde_json::from_str(&args[0]).unwrap()
+    }
+
+    pub async fn expect_close(&mut self) -> Option<CloseFrame<'static>> {
+        match self.recv().await {
+            Message::Close(frame) => frame,
+            msg => panic!("expected a close, got {:?}", msg),
+        }
+    }
+
+    pub async fn close(mut self) {
+        // Already closed when the server started the close handshake.
+        let _ = self.socket.close(None).await;
+        // Wait for the server to answer, so it's done with the user.
+        while let Some(Ok(_)) = network(self.socket.next()).await {}
+    }
+}
@@ -0,0 +1,363 @@
+//! RFC 6455 conformance of the vendored `sturdy_tungstenite` stack, following the sections of
+//! the Autobahn fuzzing client cases, run offline against an in memory stream.
+mod common;
+
+use common::mock::{client_frame, encode, server, server_with_config, written, MockStream};
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::error::{CapacityError, Error, ProtocolError};
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::protocol::frame::{
+    coding::{CloseCode, Control, Data, OpCode},
+    CloseFrame, Frame, FrameHeader,
+};
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::protocol::{Role, WebSocketConfig};
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::{Message, WebSocket};
+
+const TEXT: OpCode = OpCode::Data(Data::Text);
+const BINARY: OpCode = OpCode::Data(Data::Binary);
+const CONTINUE: OpCode = OpCode::Data(Data::Continue);
+const PING: OpCode = OpCode::Control(Control::Ping);
+const PONG: OpCode = OpCode::Control(Control::Pong);
+const CLOSE: OpCode = OpCode::Control(Control::Close);
+
+fn expect_protocol_error(ws: &mut WebSocket<MockStream>, expected: ProtocolError) {
+    match ws.read() {
+        Err(Error::Protocol(err)) => assert_eq!(err, expected),
+        other => panic!("expected {:?}, got {:?}", expected, other),
+    }
+}
+
+fn close_payload(code: u16, reason: &[u8]) -> Vec<u8> {
+    let mut payload = code.to_be_bytes().to_vec();
+    payload.extend_from_slice(reason);
+    payload
+}
+
+/// The close frame the server answered with, after driving the close handshake to its end.
+fn close_reply(ws: &mut WebSocket<MockStream>) -> Frame {
+    assert!(matches!(ws.flush(), Err(Error::ConnectionClosed)));
+    let mut frames = written(ws);
+    assert_eq!(frames.len(), 1, "{:?}", frames);
+    let frame = frames.remove(0);
+    assert_eq!(frame.header().opcode, CLOSE);
+    frame
+}
+
+fn reply_code(frame: &Frame) -> u16 {
+    u16::from_be_bytes([frame.payload()[0], frame.payload()[1]])
+}
+
+// 1.* Framing
+
+#[test]
+fn payload_lengths_around_the_length_encodings() {
+    for len in [0, 1, 125, 126, 127, 128, 65535, 65536, 65537] {
+        let text = "*".repeat(len);
+        let binary = vec![0xfe; len];
+        let mut ws = server([
+            client_frame(TEXT, true, text.clone()),
+            client_frame(BINARY, true, binary.clone()),
+        ]);
+        assert_eq!(ws.read().unwrap(), Message::Text(text));
+        assert_eq!(ws.read().unwrap(), Message::Binary(binary));
+    }
+}
+
+#[test]
+fn bytes_arriving_one_at_a_time() {
+    let bytes = encode([client_frame(TEXT, true, "Hello, world!")]);
+    let chunks = bytes.into_iter().map(|byte| vec![byte]).collect();
+    let mut ws = WebSocket::from_raw_socket(MockStream::chunked(chunks), Role::Server, None);
+    assert_eq!(ws.read().unwrap(), Message::Text("Hello, world!".into()));
+}
+
+// 2.* Pings/Pongs
+
+#[test]
+fn ping_is_answered_with_the_same_payload() {
+    for payload in [vec![], vec![0xab; 125], b"Hello".to_vec()] {
+        let mut ws = server([client_frame(PING, true, payload.clone())]);
+        assert_eq!(ws.read().unwrap(), Message::Ping(payload.clone()));
+        ws.flush().unwrap();
+        assert_eq!(written(&ws), [Frame::pong(payload)]);
+    }
+}
+
+#[test]
+fn ping_payload_over_125_bytes_fails() {
+    let mut ws = server([client_frame(PING, true, vec![0; 126])]);
+    expect_protocol_error(&mut ws, ProtocolError::ControlFrameTooBig);
+}
+
+#[test]
+fn unsolicited_pong_is_not_answered() {
+    let mut ws = server([client_frame(PONG, true, "unsolicited")]);
+    assert_eq!(ws.read().unwrap(), Message::Pong(b"unsolicited".to_vec()));
+    ws.flush().unwrap();
+    assert!(written(&ws).is_empty());
+}
+
+#[test]
+fn fragmented_ping_fails() {
+    let mut ws = server([client_frame(PING, false, "ping")]);
+    expect_protocol_error(&mut ws, ProtocolError::FragmentedControlFrame);
+}
+
+// 3.* Reserved bits
+
+#[test]
+fn reserved_bits_without_extension_fail() {
+    let set_rsv: [fn(&mut FrameHeader); 3] = [
+        |header| header.rsv1 = true,
+        |header| header.rsv2 = true,
+        |header| header.rsv3 = true,
+    ];
+    for set in set_rsv {
+        for opcode in [TEXT, BINARY, PING] {
+            let mut frame = client_frame(opcode, true, "data");
+            set(frame.header_mut());
+            let mut ws = server([frame]);
+            expect_protocol_error(&mut ws, ProtocolError::NonZeroReservedBits);
+        }
+    }
+}
+
+// 4.* Opcodes
+
+#[test]
+fn reserved_opcodes_fail() {
+    for code in (3..=7).chain(0xb..=0xf) {
+        let mut ws = server([client_frame(OpCode::from(code), true, "data")]);
+        expect_protocol_error(&mut ws, ProtocolError::InvalidOpcode(code));
+    }
+}
+
+// 5.* Fragmentation
+
+#[test]
+fn fragmented_text_is_reassembled() {
+    let mut ws = server([
+        client_frame(TEXT, false, "frag"),
+        client_frame(CONTINUE, false, ""),
+        client_frame(CONTINUE, false, "ment"),
+        client_frame(CONTINUE, true, "ed"),
+    ]);
+    assert_eq!(ws.read().unwrap(), Message::Text("fragmented".into()));
+}
+
+#[test]
+fn ping_between_fragments_is_answered() {
+    let mut ws = server([
+        client_frame(BINARY, false, [1, 2]),
+        client_frame(PING, true, "ping"),
+        client_frame(CONTINUE, true, [3]),
+    ]);
+    assert_eq!(ws.read().unwrap(), Message::Ping(b"ping".to_vec()));
+    assert_eq!(ws.read().unwrap(), Message::Binary(vec![1, 2, 3]));
+    ws.flush().unwrap();
+    assert_eq!(written(&ws), [Frame::pong(b"ping".to_vec())]);
+}
+
+#[test]
+fn continuation_without_a_message_fails() {
+    for is_final in [true, false] {
+        let mut ws = server([client_frame(CONTINUE, is_final, "data")]);
+        expect_protocol_error(&mut ws, ProtocolError::UnexpectedContinueFrame);
+    }
+}
+
+#[test]
+fn new_message_before_the_last_fragment_fails() {
+    let mut ws = server([
+        client_frame(TEXT, false, "frag"),
+        client_frame(TEXT, true, "ment"),
+    ]);
+    expect_protocol_error(&mut ws, ProtocolError::ExpectedFragment(Data::Text));
+}
+
+// 6.* UTF-8 handling
+
+const KOSME: &str = "κόσμε";
+
+#[test]
+fn utf8_split_inside_characters_is_reassembled() {
+    let bytes = KOSME.as_bytes();
+    for split in 0..=bytes.len() {
+        let mut ws = server([
+            client_frame(TEXT, false, &bytes[..split]),
+            client_frame(CONTINUE, true, &bytes[split..]),
+        ]);
+        assert_eq!(ws.read().unwrap(), Message::Text(KOSME.into()));
+    }
+}
+
+#[test]
+fn invalid_utf8_fails() {
+    let invalid: [&[u8]; 5] = [
+        // UTF-16 surrogate.
+        &[0xed, 0xa0, 0x80],
+        // Overlong encoding of '/'.
+        &[0xc0, 0xaf],
+        // Past U+10FFFF.
+        &[0xf4, 0x90, 0x80, 0x80],
+        // Lone continuation byte.
+        &[0x80],
+        // Truncated sequence at the end of the message.
+        &[0xce, 0xba, 0xe1],
+    ];
+    for bytes in invalid {
+        let mut ws = server([client_frame(TEXT, true, bytes)]);
+        assert!(matches!(ws.read(), Err(Error::Utf8)), "{:?}", bytes);
+    }
+}
+
+#[test]
+fn invalid_utf8_fails_fast() {
+    // The message never ends, the first fragment is enough to fail.
+    let mut ws = server([client_frame(TEXT, false, [0xce, 0xba, 0xed, 0xa0, 0x80])]);
+    assert!(matches!(ws.read(), Err(Error::Utf8)));
+}
+
+// 7.* Close handling
+
+#[test]
+fn close_without_payload_is_echoed() {
+    let mut ws = server([client_frame(CLOSE, true, [])]);
+    assert_eq!(ws.read().unwrap(), Message::Close(None));
+    assert!(close_reply(&mut ws).payload().is_empty());
+}
+
+#[test]
+fn allowed_close_codes_are_echoed() {
+    for code in [
+        1000, 1001, 1002, 1003, 1007, 1008, 1009, 1010, 1011, 3000, 3999, 4000, 4999,
+    ] {
+        let mut ws = server([client_frame(CLOSE, true, close_payload(code, b"bye"))]);
+        let expected = CloseFrame {
+            code: CloseCode::from(code),
+            reason: "bye".into(),
+        };
+        assert_eq!(ws.read().unwrap(), Message::Close(Some(expected)));
+        assert_eq!(reply_code(&close_reply(&mut ws)), code);
+    }
+}
+
+#[test]
+fn disallowed_close_codes_are_answered_with_protocol_error() {
+    for code in [
+        0, 999, 1004, 1005, 1006, 1015, 1016, 1100, 2000, 2999, 5000, 65535,
+    ] {
+        let mut ws = server([client_frame(CLOSE, true, close_payload(code, b""))]);
+        assert!(matches!(ws.read(), Ok(Message::Close(Some(_)))));
+        assert_eq!(reply_code(&close_reply(&mut ws)), 1002, "code {}", code);
+    }
+}
+
+#[test]
+fn close_code_allowed_matches_rfc() {
+    for code in 0..=u16::MAX {
+        let allowed = matches!(code, 1000..=1003 | 1007..=1013 | 3000..=4999);
+        assert_eq!(CloseCode::from(code).is_allowed(), allowed, "code {}", code);
+    }
+}
+
+#[test]
+fn close_with_one_byte_payload_fails() {
+    let mut ws = server([client_frame(CLOSE, true, [0x03])]);
+    expect_protocol_error(&mut ws, ProtocolError::InvalidCloseSequence);
+}
+
+#[test]
+fn close_with_invalid_utf8_reason_fails() {
+    let mut ws = server([client_frame(
+        CLOSE,
+        true,
+        close_payload(1000, &[0xed, 0xa0, 0x80]),
+    )]);
+    assert!(matches!(ws.read(), Err(Error::Utf8)));
+}
+
+#[test]
+fn close_payload_over_125_bytes_fails() {
+    let mut ws = server([client_frame(CLOSE, true, close_payload(1000, &[b'a'; 124]))]);
+    expect_protocol_error(&mut ws, ProtocolError::ControlFrameTooBig);
+}
+
+#[test]
+fn data_after_close_is_dropped() {
+    let mut ws = server([
+        client_frame(CLOSE, true, close_payload(1000, b"")),
+        client_frame(TEXT, true, "too late"),
+    ]);
+    assert!(matches!(ws.read(), Ok(Message::Close(Some(_)))));
+    assert!(matches!(ws.read(), Err(Error::ConnectionClosed)));
+    assert!(matches!(ws.read(), Err(Error::AlreadyClosed)));
+}
+
+#[test]
+fn closing_without_handshake_fails() {
+    let mut ws = server([client_frame(TEXT, false, "frag")]);
+    expect_protocol_error(&mut ws, ProtocolError::ResetWithoutClosingHandshake);
+}
+
+// 9.* Limits
+
+#[test]
+fn frame_over_max_frame_size_fails() {
+    let config = WebSocketConfig {
+        max_frame_size: Some(1024),
+        ..WebSocketConfig::default()
+    };
+    let mut ws = server_with_config([client_frame(BINARY, true, vec![0; 1025])], config);
+    assert!(matches!(
+        ws.read(),
+        Err(Error::Capacity(CapacityError::MessageTooLong {
+            size: 1025,
+            max_size: 1024
+        }))
+    ));
+}
+
+#[test]
+fn fragmented_message_over_max_message_size_fails() {
+    let config = WebSocketConfig {
+        max_message_size: Some(1024),
+        ..WebSocketConfig::default()
+    };
+    let mut ws = server_with_config(
+        [
+            client_frame(TEXT, false, "a".repeat(1000)),
+            client_frame(CONTINUE, true, "a".repeat(25)),
+        ],
+        config,
+    );
+    assert!(matches!(
+        ws.read(),
+        Err(Error::Capacity(CapacityError::MessageTooLong {
+            size: 1025,
+            max_size: 1024
+        }))
+    ));
+}
+
+// Masking
+
+#[test]
+fn unmasked_client_frame_fails() {
+    let mut frame = client_frame(TEXT, true, "unmasked");
+    frame.header_mut().mask = None;
+    let mut ws = server([frame.clone()]);
+    expect_protocol_error(&mut ws, ProtocolError::UnmaskedFrameFromClient);
+
+    let config = WebSocketConfig {
+        accept_unmasked_frames: true,
+        ..WebSocketConfig::default()
+    };
+    let mut ws = server_with_config([frame], config);
+    assert_eq!(ws.read().unwrap(), Message::Text("unmasked".into()));
+}
+
+#[test]
+fn masked_server_frame_fails() {
+    let bytes = encode([client_frame(TEXT, true, "masked")]);
+    let mut ws = WebSocket::from_raw_socket(MockStream::new(bytes), Role::Client, None);
+    expect_protocol_error(&mut ws, ProtocolError::MaskedFrameFromServer);
+}
@@ -0,0 +1,135 @@
+//! Property tests for frame encoding and decoding round trips.
+mod common;
+
+use std::io::Cursor;
+
+use common::mock::{client_frame, decode, encode, MockStream};
+use proptest::prelude::*;
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::protocol::frame::{
+    coding::{Data, OpCode},
+    FrameHeader,
+};
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::protocol::Role;
+use sturdy_spoon::sturdy_ws::sturdy_tungstenite::{Message, WebSocket};
+
+fn opcode() -> impl Strategy<Value = OpCode> {
+    prop::sample::select(vec![0u8, 1, 2, 8, 9, 10]).prop_map(OpCode::from)
+}
+
+fn header() -> impl Strategy<Value = FrameHeader> {
+    (any::<[bool; 4]>(), opcode(), any::<Option<[u8; 4]>>()).prop_map(
+        |([is_final, rsv1, rsv2, rsv3], opcode, mask)| FrameHeader {
+            is_final,
+            rsv1,
+            rsv2,
+            rsv3,
+            opcode,
+            mask,
+        },
+    )
+}
+
+fn length() -> impl Strategy<Value = u64> {
+    prop_oneof![0..=125u64, 126..=65535u64, 65536..=u64::MAX]
+}
+
+fn message() -> impl Strategy<Value = Message> {
+    prop_oneof![
+        any::<String>().prop_map(Message::Text),
+        prop::collection::vec(any::<u8>(), 0..2048).prop_map(Message::Binary),
+    ]
+}
+
+/// Cuts `bytes` at the given fractions of its length.
+fn split(bytes: &[u8], mut cuts: Vec<f64>) -> Vec<Vec<u8>> {
+    cuts.sort_by(f64::total_cmp);
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for cut in cuts {
+        let end = ((bytes.len() as f64 * cut) as usize).max(start);
+        pieces.push(bytes[start..end].to_vec());
+        start = end;
+    }
+    pieces.push(bytes[start..].to_vec());
+    pieces
+}
+
+proptest! {
+    #[test]
+    fn header_round_trip(header in header(), length in length()) {
+        let mut bytes = Vec::new();
+        header.format(length, &mut bytes).unwrap();
+        prop_assert_eq!(bytes.len(), header.len(length));
+
+        let mut cursor = Cursor::new(&bytes);
+        let parsed = FrameHeader::parse(&mut cursor).unwrap();
+        prop_assert_eq!(parsed, Some((header, length)));
+        prop_assert_eq!(cursor.position() as usize, bytes.len());
+    }
+
+    #[test]
+    fn truncated_header_is_incomplete(header in header(), length in length(), cut in 0.0..1.0f64) {
+        let mut bytes = Vec::new();
+        header.format(length, &mut bytes).unwrap();
+        let bytes = &bytes[..(bytes.len() as f64 * cut) as usize];
+
+        let mut cursor = Cursor::new(bytes);
+        prop_assert_eq!(FrameHeader::parse(&mut cursor).unwrap(), None);
+        prop_assert_eq!(cursor.position(), 0);
+    }
+
+    #[test]
+    fn masked_frame_round_trip(
+        opcode in opcode(),
+        is_final in any::<bool>(),
+        payload in prop::collection::vec(any::<u8>(), 0..70000),
+    ) {
+        let frame = client_frame(opcode, is_final, payload.clone());
+        let mut frames = decode(&encode([frame]));
+        prop_assert_eq!(frames.len(), 1);
+        let frame = frames.remove(0);
+        prop_assert_eq!(frame.header().opcode, opcode);
+        prop_assert_eq!(frame.header().is_final, is_final);
+        prop_assert_eq!(frame.payload(), &payload);
+    }
+
+    /// Whatever the fragments and however the bytes arrive, the server reads the message back.
+    #[test]
+    fn fragmented_message_round_trip(
+        message in message(),
+        fragments in prop::collection::vec(0.0..1.0f64, 0..8),
+        chunks in prop::collection::vec(0.0..1.0f64, 0..16),
+    ) {
+        let (data, payload) = match &message {
+            Message::Text(text) => (Data::Text, text.as_bytes()),
+            Message::Binary(binary) => (Data::Binary, binary.as_slice()),
+            _ => unreachable!(),
+        };
+        let fragments = split(payload, fragments);
+        let last = fragments.len() - 1;
+        let frames = fragments.into_iter().enumerate().map(|(i, fragment)| {
+            let opcode = if i == 0 { OpCode::Data(data) } else { OpCode::Data(Data::Continue) };
+            client_frame(opcode, i == last, fragment)
+        });
+
+        let stream = MockStream::chunked(split(&encode(frames), chunks));
+        let mut ws = WebSocket::from_raw_socket(stream, Role::Server, None);
+        prop_assert_eq!(ws.read().unwrap(), message);
+    }
+
+    /// Messages written by the server are read back by a client.
+    #[test]
+    fn written_message_round_trip(messages in prop::collection::vec(message(), 1..8)) {
+        let mut server = WebSocket::from_raw_socket(MockStream::default(), Role::Server, None);
+        for message in &messages {
+            server.write(message.clone()).unwrap();
+        }
+        server.flush().unwrap();
+
+        let bytes = std::mem::take(&mut server.get_mut().output);
+        let mut client = WebSocket::from_raw_socket(MockStream::new(bytes), Role::Client, None);
+        for message in messages {
+            prop_assert_eq!(client.read().unwrap(), message);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use common::mock_issuer::MockIssuer;
+use common::TestServer;
+use hyper::{Method, StatusCode};
+use serde_json::{json, Value};
+
+const CLIENT_ID: &str = "sturdy-spoon";
+const CLIENT_SECRET: &str = "client secret";
+
+/// The only test of the file, the variables are read when the server starts and are the same for
+/// the whole process.
+#[tokio::test(start_paused = true)]
+async fn only_signed_in_browsers_create_rooms() {
+    let issuer = MockIssuer::start(CLIENT_ID, CLIENT_SECRET).await;
+    std::env::set_var("OIDC_ISSUER", issuer.url());
+    std::env::set_var("OIDC_CLIENT_ID", CLIENT_ID);
+    std::env::set_var("OIDC_CLIENT_SECRET", CLIENT_SECRET);
+    std::env::set_var(
+        "OIDC_REDIRECT_URL",
+        "http://spoon.example.com/auth/oidc/callback",
+    );
+    std::env::set_var("OIDC_REQUIRE_FOR_CREATE", "1");
+    let server = TestServer::start().await;
+
+    let mut employee = server.browser();
+    let (status, body) = employee.create_room_with(json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", body);
+
+    // The browser is sent to the issuer and comes back with a code.
+    let (status, location) = employee
+        .get_redirect("/auth/oidc/login?return_to=/room/create-page")
+        .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let (code, state) = issuer.authorize(&location.unwrap(), "jdoe", "Jane Doe");
+
+    // Codes are only good with the state of a login the browser started itself.
+    let (status, _) = server
+        .browser()
+        .get_redirect(&format!(
+            "/auth/oidc/callback?code={}&state={}",
+            code, state
+        ))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, location) = employee
+        .get_redirect(&format!(
+            "/auth/oidc/callback?code={}&state={}",
+            code, state
+        ))
+        .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(location.as_deref(), Some("/room/create-page"));
+    assert!(employee.cookie("sso").is_some());
+    let (status, body) = employee.request(Method::GET, "/auth/oidc/me", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let me: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(me, json!({"name": "Jane Doe", "email": "jdoe@example.com"}));
+
+    // Signed in, rooms can be created and anyone can still join them.
+    let room_id = employee.create_room(2, false).await;
+    let mut guest_browser = server.browser();
+    assert_eq!(guest_browser.join(&room_id).await, Ok(false));
+    let mut guest = guest_browser.connect().await;
+    guest.send("join_room", &[&room_id, "guest"]).await;
+    assert_eq!(guest.expect_video_data().await["permission"], 0);
+    guest.close().await;
+
+    // Tokens not signed by the issuer's keys, and open redirects, are refused.
+    let mut intruder = server.browser();
+    let (_, location) = intruder
+        .get_redirect("/auth/oidc/login?return_to=//evil.example.com")
+        .await;
+    let (code, state) = issuer.authorize(&location.unwrap(), "intruder", "Intruder");
+    issuer.forge_tokens(true);
+    let (status, _) = intruder
+        .get_redirect(&format!(
+            "/auth/oidc/callback?code={}&state={}",
+            code, state
+        ))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    issuer.forge_tokens(false);
+    let (_, location) = intruder
+        .get_redirect("/auth/oidc/login?return_to=//evil.example.com")
+        .await;
+    let (code, state) = issuer.authorize(&location.unwrap(), "intruder", "Intruder");
+    let (status, location) = intruder
+        .get_redirect(&format!(
+            "/auth/oidc/callback?code={}&state={}",
+            code, state
+        ))
+        .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(location.as_deref(), Some("/"));
+    // Browsers drop the tab and would go to `//evil.example.com`.
+    let (_, location) = intruder
+        .get_redirect("/auth/oidc/login?return_to=%2F%09%2Fevil.example.com")
+        .await;
+    let (code, state) = issuer.authorize(&location.unwrap(), "intruder", "Intruder");
+    let (status, location) = intruder
+        .get_redirect(&format!(
+            "/auth/oidc/callback?code={}&state={}",
+            code, state
+        ))
+        .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(location.as_deref(), Some("/"));
+
+    // Errors from the issuer end the login.
+    let (status, _) = intruder
+        .get_redirect("/auth/oidc/callback?error=access_denied")
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = employee
+        .request(Method::POST, "/auth/oidc/logout", None)
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = employee.create_room_with(json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    server.shutdown().await;
+    issuer.shutdown().await;
+}
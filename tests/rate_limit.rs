@@ -0,0 +1,95 @@
+mod common;
+
+use std::time::Duration;
+
+use common::TestServer;
+use hyper::{Method, StatusCode};
+use serde_json::json;
+use sturdy_spoon::sturdy_ws::{CloseCode, Message};
+
+#[tokio::test(start_paused = true)]
+async fn room_creation_is_limited_per_address() {
+    let server = TestServer::start().await;
+
+    for _ in 0..10 {
+        server.browser().create_room(2, false).await;
+    }
+    let (status, _) = server.browser().create_room_with(json!({})).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // The rest of the API has its own, larger, quota.
+    let (status, _) = server
+        .browser()
+        .request(Method::GET, "/room/not-a-room", None)
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // A token comes back every six seconds.
+    tokio::time::advance(Duration::from_secs(6)).await;
+    server.browser().create_room(2, false).await;
+
+    server.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn flooding_packets_closes_the_socket() {
+    let server = TestServer::start().await;
+
+    let room_id = server.browser().create_room(2, false).await;
+    let mut guest = server.browser().connect().await;
+    guest.send("join_room", &[&room_id, "guest"]).await;
+    guest.expect_video_data().await;
+
+    for _ in 0..25 {
+        guest.send("state", &["0", "0"]).await;
+    }
+    let close = loop {
+        match guest.recv().await {
+            Message::Close(frame) => break frame.unwrap(),
+            Message::Text(_) => continue,
+            msg => panic!("expected a close, got {:?}", msg),
+        }
+    };
+    assert_eq!(close.code, CloseCode::Policy);
+    assert_eq!(close.reason, "rate limit exceeded");
+    guest.close().await;
+
+    // Packets sent at a normal pace are fine.
+    tokio::time::advance(Duration::from_secs(1)).await;
+    let mut guest = server.browser().connect().await;
+    guest.send("join_room", &[&room_id, "guest"]).await;
+    guest.expect_video_data().await;
+    for _ in 0..3 {
+        guest.send("state", &["0", "0"]).await;
+        guest.recv().await;
+    }
+
+    server.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn flooders_not_answering_the_close_are_dropped() {
+    let server = TestServer::start().await;
+
+    let room_id = server.browser().create_room(1, false).await;
+    let mut flooder = server.browser().connect().await;
+    flooder.send("join_room", &[&room_id, "flooder"]).await;
+    flooder.expect_video_data().await;
+    for _ in 0..25 {
+        flooder.send("state", &["0", "0"]).await;
+    }
+    loop {
+        if let Message::Close(_) = flooder.recv().await {
+            break;
+        }
+    }
+
+    // The flooder never answers, its slot is freed within seconds all the same.
+    tokio::time::advance(Duration::from_secs(4)).await;
+    let mut guest = server.browser().connect().await;
+    guest.send("join_room", &[&room_id, "guest"]).await;
+    guest.expect_video_data().await;
+
+    drop(flooder);
+    server.shutdown().await;
+}
@@ -0,0 +1,539 @@
+mod common;
+
+use std::time::Duration;
+
+use common::TestServer;
+use hyper::{Method, StatusCode};
+use serde_json::{json, Value};
+use sturdy_spoon::sturdy_ws::CloseCode;
+
+/// An empty room is removed once `CLIENT_TIMEOUT` is over.
+const ROOM_SHUTDOWN_DELAY: Duration = Duration::from_secs(2 * 60 + 1);
+
+#[tokio::test(start_paused = true)]
+async fn room_lifecycle() {
+    let server = TestServer::start().await;
+
+    let mut owner_browser = server.browser();
+    let room_id = owner_browser.create_room(3, false).await;
+    assert_eq!(owner_browser.join(&room_id).await, Ok(true));
+    let mut owner = owner_browser.connect().await;
+    assert_eq!(
+        owner.expect_video_data().await,
+        json!({
+            "url": "https://example.com/video.mp4",
+            "cc_url": "",
+            "time": 0,
+            "state": 0,
+            "current_player": 0,
+            "permission": 3,
+        })
+    );
+
+    let mut guest_browser = server.browser();
+    assert_eq!(guest_browser.join(&room_id).await, Ok(false));
+    let mut guest = guest_browser.connect().await;
+    guest.send("join_room", &[&room_id, "guest"]).await;
+    assert_eq!(guest.expect_video_data().await["permission"], 0);
+    let (packet_type, args) = owner.recv_packet().await;
+    assert_eq!(
+        (packet_type.as_str(), args[0].as_str()),
+        ("joined", "guest")
+    );
+    let guest_id = args[1].clone();
+
+    // The owner controls the video for everyone.
+    owner.send("play", &["0"]).await;
+    owner.expect("play", &["0"]).await;
+    guest.expect("play", &["0"]).await;
+
+    // Ten seconds in, a guest in sync is told so and one drifting away gets the room's state.
+    tokio::time::advance(Duration::from_secs(10)).await;
+    guest.send("state", &["10", "1"]).await;
+    guest.expect("state_ok", &[]).await;
+    guest.send("state", &["30", "1"]).await;
+    guest.expect("state", &["10000", "1"]).await;
+
+    // The owner drifting away moves the whole room instead.
+    owner.send("state", &["40", "1"]).await;
+    owner.expect("state", &["40000", "1"]).await;
+    guest.expect("state", &["40000", "1"]).await;
+
+    // Without global control the guest can't control the video, it's only sent the room's data.
+    guest.send("pause", &["41"]).await;
+    let data = guest.expect_video_data().await;
+    assert_eq!((&data["time"], &data["state"]), (&json!(40000), &json!(1)));
+
+    owner.send("pause", &["42.5"]).await;
+    owner.expect("pause", &["42500"]).await;
+    guest.expect("pause", &["42500"]).await;
+
+    owner.send("seek", &["60"]).await;
+    owner.expect("seek", &["60000"]).await;
+    guest.expect("seek", &["60000"]).await;
+
+    guest.close().await;
+    owner.expect("left", &["guest", &guest_id]).await;
+    owner.close().await;
+
+    // The room waits a bit for its users to come back before going away.
+    let mut late_browser = server.browser();
+    assert_eq!(late_browser.join(&room_id).await, Ok(false));
+    tokio::time::sleep(ROOM_SHUTDOWN_DELAY).await;
+    assert_eq!(
+        late_browser.join(&room_id).await,
+        Err(StatusCode::BAD_REQUEST)
+    );
+
+    server.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn global_control_lets_guests_control() {
+    let server = TestServer::start().await;
+
+    let mut owner_browser = server.browser();
+    let room_id = owner_browser.create_room(2, true).await;
+
+    let mut first = server.browser().connect().await;
+    first.send("join_room", &[&room_id, "first"]).await;
+    assert_eq!(first.expect_video_data().await["permission"], 1);
+    let mut second = server.browser().connect().await;
+    second.send("join_room", &[&room_id, "second"]).await;
+    assert_eq!(second.expect_video_data().await["permission"], 1);
+    let (packet_type, _) = first.recv_packet().await;
+    assert_eq!(packet_type, "joined");
+
+    second.send("play", &["5"]).await;
+    first.expect("play", &["5000"]).await;
+    second.expect("play", &["5000"]).await;
+
+    // The room is full, the owner can't get back in.
+    let mut third = owner_browser.connect().await;
+    third.send("join_room", &[&room_id, "third"]).await;
+    assert!(third.expect_close().await.is_some());
+
+    server.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_notifies_users() {
+    let server = TestServer::start().await;
+
+    let room_id = server.browser().create_room(2, false).await;
+    let mut guest = server.browser().connect().await;
+    guest.send("join_room", &[&room_id, "guest"]).await;
+    guest.expect_video_data().await;
+    let mut events = server
+        .browser()
+        .events(&format!("/room/{}/events", room_id))
+        .await
+        .unwrap();
+    events.next_event().await.unwrap();
+
+    let shutdown = tokio::spawn(server.shutdown());
+    let close = guest.expect_close().await.unwrap();
+    assert_eq!(close.code, CloseCode::Away);
+    assert_eq!(close.reason, "server restarting");
+    guest.close().await;
+    // The event streams end as well, hyper would wait for them otherwise.
+    assert_eq!(events.next_event().await, None);
+
+    // Every user left, the server doesn't wait for the drain timeout.
+    tokio::time::timeout(Duration::from_secs(1), shutdown)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn join_checks_room_ids() {
+    let server = TestServer::start().await;
+
+    let room_id = server.browser().create_room(2, false).await;
+    let mut guest = server.browser();
+    let (status, body) = guest
+        .request(
+            Method::POST,
+            "/room/join",
+            Some(json!({ "room_id": room_id })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let joined: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(joined["room_id"], room_id.as_str());
+
+    let not_an_id = format!("{}!", &room_id[1..]);
+    // Anything that isn't a string can't be a room.
+    let (status, _) = guest
+        .request(
+            Method::POST,
+            "/room/join",
+            Some(json!({ "room_id": room_id.as_bytes() })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = guest
+        .request(
+            Method::POST,
+            "/room/join",
+            Some(json!({ "room_id": not_an_id })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(guest.join(&not_an_id).await, Err(StatusCode::BAD_REQUEST));
+
+    let mut client = guest.connect().await;
+    client.send("join_room", &[&not_an_id, "guest"]).await;
+    assert!(client.expect_close().await.is_some());
+
+    server.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn rooms_are_found_by_short_code_and_slug() {
+    let server = TestServer::start().await;
+
+    let mut owner_browser = server.browser();
+    let (status, body) = owner_browser
+        .create_room_with(json!({ "short_code": true, "slug": "Movie-Night" }))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let room: Value = serde_json::from_str(&body).unwrap();
+    let room_id = room["id"].as_str().unwrap().to_owned();
+    let short_code = room["short_code"].as_str().unwrap().to_owned();
+    assert_eq!(room["slug"], "movie-night");
+    let parts: Vec<&str> = short_code.split('-').collect();
+    assert_eq!(parts.len(), 3, "{}", short_code);
+    assert!(parts[2].parse::<u8>().is_ok(), "{}", short_code);
+
+    assert_eq!(owner_browser.join(&short_code).await, Ok(true));
+    let mut guest = server.browser();
+    for alias in [
+        short_code.to_lowercase().as_str(),
+        "movie-night",
+        "MOVIE-NIGHT",
+    ] {
+        assert_eq!(guest.join(alias).await, Ok(false));
+        let (status, body) = guest
+            .request(
+                Method::POST,
+                "/room/join",
+                Some(json!({ "room_id": alias })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let joined: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(joined["room_id"], room_id.as_str());
+
+        let mut events = guest
+            .events(&format!("/room/{}/events", alias))
+            .await
+            .unwrap();
+        let (event, _) = events.next_event().await.unwrap();
+        assert_eq!(event, "video_data");
+    }
+
+    let (status, _) = server
+        .browser()
+        .create_room_with(json!({ "slug": "movie-night" }))
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    for bad_slug in ["ws", "-movie", "a b c", "ab", &"a".repeat(33)] {
+        let (status, _) = server
+            .browser()
+            .create_room_with(json!({ "slug": bad_slug }))
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", bad_slug);
+    }
+
+    // Removing the room releases its aliases.
+    let mut owner = owner_browser.connect().await;
+    owner.send("join_room", &[&room_id, "owner"]).await;
+    owner.expect_video_data().await;
+    owner.close().await;
+    tokio::time::sleep(ROOM_SHUTDOWN_DELAY).await;
+    assert_eq!(
+        guest.join("movie-night").await,
+        Err(StatusCode::BAD_REQUEST)
+    );
+    assert_eq!(guest.join(&short_code).await, Err(StatusCode::BAD_REQUEST));
+    let (status, body) = server
+        .browser()
+        .create_room_with(json!({ "slug": "movie-night" }))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    server.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn room_page_escapes_the_room_name() {
+    let server = TestServer::start().await;
+
+    let name = "</script><script>alert('pwned')</script>\"<b>";
+    let mut browser = server.browser();
+    let (status, body) = browser.create_room_with(json!({ "name": name })).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let room: Value = serde_json::from_str(&body).unwrap();
+    let room_id = room["id"].as_str().unwrap();
+
+    let (status, page) = browser
+        .request(Method::GET, &format!("/room/{}", room_id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!page.contains("<script>alert"), "{}", page);
+    assert!(!page.contains("\"<b>"), "{}", page);
+    assert!(page.contains("let autoConnect = true;"), "{}", page);
+
+    // The name makes it to the page, only escaped.
+    let data = page
+        .split("let room_data = ")
+        .nth(1)
+        .and_then(|rest| rest.lines().next())
+        .and_then(|line| line.strip_suffix(';'))
+        .unwrap();
+    let data: Value = serde_json::from_str(data).unwrap();
+    assert_eq!(data["name"], name);
+    assert_eq!(data["room_id"], room_id);
+
+    server.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn create_and_join_validate_their_input() {
+    let server = TestServer::start().await;
+
+    let (status, body) = server
+        .browser()
+        .create_room_with(json!({
+            "name": " ",
+            "creator_name": "Admin",
+            "video_url": "javascript:alert(1)",
+            "cc_url": "ftp://example.com/subs.vtt",
+            "max_users": 0,
+        }))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    let fields: Vec<&str> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|err| err["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        fields,
+        ["name", "creator_name", "video_url", "cc_url", "max_users"]
+    );
+
+    let (status, _) = server
+        .browser()
+        .create_room_with(json!({ "name": "a".repeat(65) }))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = server
+        .browser()
+        .create_room_with(json!({ "max_users": -2 }))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, body) = server
+        .browser()
+        .create_room_with(json!({ "player_index": 9 }))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["errors"][0]["field"], "player_index");
+
+    // So does changing the video through the control endpoint.
+    let mut owner = server.browser();
+    let room_id = owner.create_room(3, false).await;
+    let video_path = format!("/room/{}/video", room_id);
+    let (status, body) = owner
+        .request(
+            Method::POST,
+            &video_path,
+            Some(json!({
+                "video_url": "javascript:alert(1)",
+                "cc_url": "data:text/vtt,",
+                "player_index": 9,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["errors"].as_array().unwrap().len(), 3);
+    let (status, body) = owner
+        .request(
+            Method::POST,
+            &video_path,
+            Some(json!({
+                "video_url": "https://example.com/other.mp4",
+                "cc_url": "",
+                "player_index": 0,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // The names users join with go through the same rules.
+    for name in ["System", "a\u{7}b", &"a".repeat(33)] {
+        let mut client = server.browser().connect().await;
+        client.send("join_room", &[&room_id, name]).await;
+        let close = client.expect_close().await.unwrap();
+        assert_eq!(close.code, CloseCode::Error, "{}", name);
+    }
+    let mut guest = server.browser().connect().await;
+    guest.send("join_room", &[&room_id, "guest"]).await;
+    guest.expect_video_data().await;
+
+    server.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn owner_token_is_bound_to_the_browser() {
+    let server = TestServer::start().await;
+
+    let mut owner_browser = server.browser();
+    let room_id = owner_browser.create_room(2, false).await;
+    let token = owner_browser.cookie("owner_auth").unwrap().to_owned();
+    let device_secret = owner_browser.cookie("device_secret").unwrap().to_owned();
+    assert_eq!(owner_browser.join(&room_id).await, Ok(true));
+    let mut owner = owner_browser.connect().await;
+    owner.expect_video_data().await;
+
+    // A leaked token is useless without the secret of the browser which got it.
+    let mut thief = server.browser();
+    thief.set_cookie("owner_auth", &token);
+    assert_eq!(thief.join(&room_id).await, Ok(false));
+    let mut thief = server.browser();
+    thief.set_cookie("owner_auth", &token);
+    thief.set_cookie("device_secret", "not-the-secret");
+    assert_eq!(thief.join(&room_id).await, Ok(false));
+    let state_path = format!("/room/{}/state", room_id);
+    let mut bot = server.browser();
+    bot.set_bearer(Some(&token));
+    let (status, _) = bot.request(Method::GET, &state_path, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Bots control the room with an API token instead, it works on its own but only there.
+    let (status, body) = owner_browser
+        .request(Method::POST, &format!("/room/{}/api-token", room_id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let api_token: Value = serde_json::from_str(&body).unwrap();
+    let api_token = api_token["token"].as_str().unwrap();
+    bot.set_bearer(Some(api_token));
+    let (status, body) = bot.request(Method::GET, &state_path, None).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    // It can't get itself a new one, it would never expire otherwise.
+    let (status, _) = bot
+        .request(Method::POST, &format!("/room/{}/api-token", room_id), None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let other_room = server.browser().create_room(2, false).await;
+    let (status, _) = bot
+        .request(Method::GET, &format!("/room/{}/state", other_room), None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let mut thief = server.browser();
+    thief.set_cookie("owner_auth", api_token);
+    assert_eq!(thief.join(&room_id).await, Ok(false));
+
+    // The browser keeps its secret, the token is only refreshed once half of its life is over.
+    assert_eq!(owner_browser.join(&room_id).await, Ok(true));
+    assert_eq!(owner_browser.cookie("owner_auth"), Some(token.as_str()));
+    tokio::time::advance(Duration::from_secs(61 * 60)).await;
+    assert_eq!(owner_browser.join(&room_id).await, Ok(true));
+    assert_eq!(
+        owner_browser.cookie("device_secret"),
+        Some(device_secret.as_str())
+    );
+    let refreshed = owner_browser.cookie("owner_auth").unwrap().to_owned();
+    assert_ne!(refreshed, token);
+
+    // Past the life of the first token, the refreshed one still works.
+    tokio::time::advance(Duration::from_secs(61 * 60)).await;
+    assert_eq!(owner_browser.join(&room_id).await, Ok(true));
+    let mut thief = server.browser();
+    thief.set_cookie("owner_auth", &token);
+    thief.set_cookie("device_secret", &device_secret);
+    assert_eq!(thief.join(&room_id).await, Ok(false));
+
+    // Owners staying in the room refresh their token without loading the page again.
+    let room_id = owner_browser.create_room(2, false).await;
+    let token = owner_browser.cookie("owner_auth").unwrap().to_owned();
+    let refresh_path = format!("/room/{}/refresh", room_id);
+    tokio::time::advance(Duration::from_secs(61 * 60)).await;
+    let (status, _) = owner_browser
+        .request(Method::POST, &refresh_path, None)
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_ne!(owner_browser.cookie("owner_auth"), Some(token.as_str()));
+    let (status, _) = thief.request(Method::POST, &refresh_path, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    owner.close().await;
+    server.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn other_origins_cant_use_the_cookies() {
+    let server = TestServer::start().await;
+
+    let mut owner = server.browser();
+    owner.set_origin(Some(&server.origin()));
+    let (status, headers, body) = owner
+        .request_with_headers(
+            Method::POST,
+            "/room/create",
+            Some(json!({
+                "name": "room",
+                "creator_name": "owner",
+                "video_url": "https://example.com/video.mp4",
+                "cc_url": "",
+                "max_users": 2,
+                "global_control": false,
+                "player_index": 0,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let room: Value = serde_json::from_str(&body).unwrap();
+    let room_id = room["id"].as_str().unwrap().to_owned();
+    let owner_cookie = headers
+        .get_all(hyper::header::SET_COOKIE)
+        .iter()
+        .map(|cookie| cookie.to_str().unwrap())
+        .find(|cookie| cookie.starts_with("owner_auth="))
+        .unwrap();
+    assert!(owner_cookie.contains("SameSite=Lax"), "{}", owner_cookie);
+    assert_eq!(owner.join(&room_id).await, Ok(true));
+
+    // A page of another site can't open the socket the owner's cookies go along with.
+    owner.set_origin(Some("https://evil.example.com"));
+    assert_eq!(owner.try_connect().await.err(), Some(StatusCode::FORBIDDEN));
+    let (status, _) = owner
+        .request(
+            Method::POST,
+            "/room/join",
+            Some(json!({ "room_id": room_id })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = owner.create_room_with(json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    // Nor can a page of the same host served over another scheme.
+    owner.set_origin(Some(&server.origin().replace("http://", "https://")));
+    let (status, _) = owner.create_room_with(json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    // Reading pages stays open to links from anywhere.
+    assert_eq!(owner.join(&room_id).await, Ok(true));
+
+    owner.set_origin(Some(&server.origin()));
+    let mut client = owner.connect().await;
+    assert_eq!(client.expect_video_data().await["permission"], 3);
+    client.close().await;
+
+    server.shutdown().await;
+}